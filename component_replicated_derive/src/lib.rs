@@ -1,10 +1,22 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, ItemStruct, parse_quote, Fields, Field};
+use syn::{parse_macro_input, ItemStruct, parse_quote, Fields, Field, LitInt};
 
 #[proc_macro_attribute]
-pub fn component_replicated(_args: TokenStream, input: TokenStream) -> TokenStream {
+pub fn component_replicated(args: TokenStream, input: TokenStream) -> TokenStream {
     let mut item_struct = parse_macro_input!(input as ItemStruct);
+    let mut id: Option<LitInt> = None;
+
+    let args_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("id") {
+            id = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported component_replicated argument, expected `id = ...`"))
+        }
+    });
+
+    parse_macro_input!(args with args_parser);
 
     fn process_fields<'a, I>(iter: I)
     where
@@ -54,10 +66,17 @@ pub fn component_replicated(_args: TokenStream, input: TokenStream) -> TokenStre
         #[reflect(Component)]
     ));
 
+    let replication_id = match id {
+        Some(id) => quote! { const REPLICATION_ID: Option<i32> = Some(#id); },
+        None => quote! {}
+    };
+
     let expanded = quote! {
         #item_struct
 
-        impl #impl_generics ComponentReplicated for #struct_name #type_generics #where_clause {}
+        impl #impl_generics ComponentReplicated for #struct_name #type_generics #where_clause {
+            #replication_id
+        }
     };
 
     TokenStream::from(expanded)
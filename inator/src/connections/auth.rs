@@ -0,0 +1,67 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use bevy::tasks::{IoTaskPool, Task};
+use bevy::tasks::futures::check_ready;
+use crate::connections::session::ResumeToken;
+
+pub type AuthFuture = Pin<Box<dyn Future<Output = bool> + Send>>;
+pub type AuthCallback = Arc<dyn Fn(Vec<u8>, Option<SocketAddr>) -> AuthFuture + Send + Sync>;
+
+/// Decides which clients get a uuid. Clients send their credentials, see
+/// `ClientConnections::set_credentials`, as the first message of every link.
+#[derive(Clone, Default)]
+pub enum Authenticator {
+    /// Every client is accepted.
+    #[default]
+    None,
+    /// An `AuthRequest` is written for every client, answer it with
+    /// `ServerConnections::accept_client` or `ServerConnections::reject_client`.
+    Manual,
+    /// The callback runs on the `IoTaskPool` and accepts the client by returning true.
+    Async(AuthCallback)
+}
+
+/// A link waiting for the authenticator.
+pub(crate) struct PendingAuth {
+    pub(crate) resume: Option<ResumeToken>,
+    pub(crate) task: Option<Task<bool>>,
+    pub(crate) accepted: Option<bool>
+}
+
+impl Authenticator {
+    pub fn new_async<F, Fut>(callback: F) -> Self
+    where
+        F: Fn(Vec<u8>, Option<SocketAddr>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static
+    {
+        Authenticator::Async(Arc::new(move |credentials, address| Box::pin(callback(credentials, address))))
+    }
+
+    pub(crate) fn start(&self, resume: Option<ResumeToken>, credentials: Vec<u8>, address: Option<SocketAddr>) -> PendingAuth {
+        let (task, accepted) = match self {
+            Authenticator::None => (None, Some(true)),
+            Authenticator::Manual => (None, None),
+            Authenticator::Async(callback) => (Some(IoTaskPool::get().spawn(callback(credentials, address))), None)
+        };
+
+        PendingAuth {
+            resume,
+            task,
+            accepted
+        }
+    }
+}
+
+impl PendingAuth {
+    pub(crate) fn poll(&mut self) -> Option<bool> {
+        if self.accepted.is_none()
+            && let Some(task) = self.task.as_mut()
+            && let Some(accepted) = check_ready(task) {
+            self.accepted = Some(accepted);
+        }
+
+        self.accepted
+    }
+}
@@ -0,0 +1,170 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How frames are compressed. Each side announces its choice when the connection starts and
+/// only compresses once the peer announced the same algorithm, so a server and a client with
+/// different settings still talk, just without compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+    /// Zstd with the given level, 1 to 22.
+    Zstd(i32)
+}
+
+/// Bytes of message frames before and after compression, for picking the algorithm and threshold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub uncompressed_sent: u64,
+    pub compressed_sent: u64,
+    pub uncompressed_received: u64,
+    pub compressed_received: u64
+}
+
+/// First byte of every frame body.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const FRAME_RAW: u8 = 0;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const FRAME_LZ4: u8 = 1;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const FRAME_ZSTD: u8 = 2;
+/// The peer's choice of `Compression`, its kind in the second byte.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const FRAME_HELLO: u8 = 0xFF;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub(crate) struct CompressionCounters {
+    uncompressed_sent: AtomicU64,
+    compressed_sent: AtomicU64,
+    uncompressed_received: AtomicU64,
+    compressed_received: AtomicU64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Compression {
+    pub(crate) fn kind(&self) -> u8 {
+        match self {
+            Compression::None => FRAME_RAW,
+            Compression::Lz4 => FRAME_LZ4,
+            Compression::Zstd(_) => FRAME_ZSTD
+        }
+    }
+
+    /// The frame body for `payload`, compressed when it is at least `threshold` bytes and
+    /// compressing made it smaller.
+    pub(crate) fn frame(&self, payload: &[u8], threshold: usize) -> Vec<u8> {
+        let compressed = match self {
+            _ if payload.len() < threshold => None,
+            Compression::None => None,
+            Compression::Lz4 => Some(lz4_flex::compress_prepend_size(payload)),
+            Compression::Zstd(level) => zstd::bulk::compress(payload, *level).ok()
+        };
+
+        let mut framed = Vec::with_capacity(payload.len() + 1);
+
+        match compressed {
+            Some(compressed) if compressed.len() < payload.len() => {
+                framed.push(self.kind());
+                framed.extend_from_slice(&compressed);
+            }
+            _ => {
+                framed.push(FRAME_RAW);
+                framed.extend_from_slice(payload);
+            }
+        }
+
+        framed
+    }
+}
+
+/// Returns `None` for unknown kinds, corrupt data or payloads that would grow past `max_size`.
+/// Zstd frames must carry their size, so only that much is allocated.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn decompress(kind: u8, data: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    match kind {
+        FRAME_RAW => Some(data.to_vec()),
+        FRAME_LZ4 => {
+            let (size, _) = lz4_flex::block::uncompressed_size(data).ok()?;
+
+            if size > max_size {
+                return None;
+            }
+
+            lz4_flex::decompress_size_prepended(data).ok()
+        }
+        FRAME_ZSTD => {
+            let size = zstd::zstd_safe::get_frame_content_size(data).ok()??;
+
+            if size > max_size as u64 {
+                return None;
+            }
+
+            zstd::bulk::decompress(data, size as usize).ok()
+        }
+        _ => None
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CompressionCounters {
+    pub(crate) fn sent(&self, uncompressed: usize, compressed: usize) {
+        self.uncompressed_sent.fetch_add(uncompressed as u64, Ordering::Relaxed);
+        self.compressed_sent.fetch_add(compressed as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn received(&self, uncompressed: usize, compressed: usize) {
+        self.uncompressed_received.fetch_add(uncompressed as u64, Ordering::Relaxed);
+        self.compressed_received.fetch_add(compressed as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            uncompressed_sent: self.uncompressed_sent.load(Ordering::Relaxed),
+            compressed_sent: self.compressed_sent.load(Ordering::Relaxed),
+            uncompressed_received: self.uncompressed_received.load(Ordering::Relaxed),
+            compressed_received: self.compressed_received.load(Ordering::Relaxed)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..4096u32).flat_map(|i| (i % 17).to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd(3)] {
+            let framed = compression.frame(&payload(), 64);
+
+            assert_eq!(framed[0], compression.kind());
+            assert_eq!(decompress(framed[0], &framed[1..], payload().len()), Some(payload()));
+        }
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        for compression in [Compression::Lz4, Compression::Zstd(3)] {
+            let framed = compression.frame(&payload(), 64);
+
+            assert_eq!(decompress(framed[0], &framed[1..], payload().len() - 1), None);
+        }
+    }
+
+    #[test]
+    fn zstd_frames_without_a_size_are_rejected() {
+        let mut encoder = zstd::bulk::Compressor::new(3).unwrap();
+
+        encoder.set_parameter(zstd::zstd_safe::CParameter::ContentSizeFlag(false)).unwrap();
+
+        let compressed = encoder.compress(&payload()).unwrap();
+
+        assert_eq!(decompress(FRAME_ZSTD, &compressed, usize::MAX), None);
+    }
+}
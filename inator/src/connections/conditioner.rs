@@ -0,0 +1,567 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use uuid::Uuid;
+use crate::connections::{ConnectionsType, MessageChannel};
+use crate::connections::compression::CompressionStats;
+use crate::connections::transport::{ClientTransport, ServerTransport, TransportState};
+use crate::systems::batching::unbatch;
+use crate::systems::messaging::{encode_message, MessageTrait, SharedMessageRoutes};
+
+/// Simulates a bad network on top of a transport. Latency and jitter are one way,
+/// so a round trip of 200 ms is a latency of 100 ms.
+///
+/// Reliable channels never lose a message, a lost message is delivered late as if it was resent.
+/// Duplication only applies to unreliable channels, reliable transports filter duplicates.
+/// Received batches are conditioned message by message, on the channel of each message.
+#[derive(Debug, Clone)]
+pub struct LinkConditionerSettings {
+    pub(crate) latency: Duration,
+    pub(crate) jitter: Duration,
+    pub(crate) loss: f32,
+    pub(crate) duplication: f32,
+    pub(crate) bandwidth: Option<u32>,
+    pub(crate) seed: u64
+}
+
+impl Default for LinkConditionerSettings {
+    fn default() -> Self {
+        LinkConditionerSettings {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            duplication: 0.0,
+            bandwidth: None,
+            seed: 0x5eed
+        }
+    }
+}
+
+impl LinkConditionerSettings {
+    pub fn new(latency: Duration, jitter: Duration, loss: f32) -> Self {
+        Self {
+            latency,
+            jitter,
+            loss: loss.clamp(0.0, 1.0),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_duplication(mut self, duplication: f32) -> Self {
+        self.duplication = duplication.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Caps each direction of every link to `bytes_per_second`.
+    pub fn with_bandwidth(mut self, bytes_per_second: u32) -> Self {
+        self.bandwidth = Some(bytes_per_second.max(1));
+        self
+    }
+
+    /// The same seed gives the same sequence of losses and delays.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// One direction of a link.
+struct LinkSimulator {
+    settings: LinkConditionerSettings,
+    rng_state: u64,
+    link_free_at: Instant,
+    last_ordered_release: Instant
+}
+
+impl LinkSimulator {
+    fn new(settings: &LinkConditionerSettings, stream: u64) -> Self {
+        let now = Instant::now();
+
+        LinkSimulator {
+            settings: settings.clone(),
+            rng_state: settings.seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+            link_free_at: now,
+            last_ordered_release: now
+        }
+    }
+
+    // splitmix64, good enough to pick losses and delays.
+    fn next_u64(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.rng_state;
+
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn delay(&mut self) -> Duration {
+        let jitter = self.settings.jitter.as_micros() as u64;
+        let jitter = if jitter > 0 { self.next_u64() % (jitter + 1) } else { 0 };
+
+        self.settings.latency + Duration::from_micros(jitter)
+    }
+
+    /// Returns when each copy of the message arrives, empty when it is lost.
+    fn schedule(&mut self, message: &dyn MessageTrait, channel: MessageChannel) -> Vec<Instant> {
+        let now = Instant::now();
+        let lost = self.settings.loss > 0.0 && self.next_f32() < self.settings.loss;
+
+        if lost && !channel.is_reliable() {
+            return Vec::new();
+        }
+
+        let mut sent_at = now;
+
+        if let Some(bandwidth) = self.settings.bandwidth {
+            let size = encode_message(message).map(|payload| payload.len()).unwrap_or(0);
+            let start = self.link_free_at.max(now);
+
+            self.link_free_at = start + Duration::from_secs_f64(size as f64 / bandwidth as f64);
+            sent_at = self.link_free_at;
+        }
+
+        let mut release = sent_at + self.delay();
+
+        if lost {
+            release += self.settings.latency * 2;
+        }
+
+        if channel == MessageChannel::ReliableOrdered {
+            release = release.max(self.last_ordered_release);
+            self.last_ordered_release = release;
+        }
+
+        let mut releases = vec![release];
+
+        if !channel.is_reliable() && self.settings.duplication > 0.0 && self.next_f32() < self.settings.duplication {
+            releases.push(sent_at + self.delay());
+        }
+
+        releases
+    }
+}
+
+struct Pending<T> {
+    release: Instant,
+    order: u64,
+    target: T,
+    message: Box<dyn MessageTrait>,
+    channel: MessageChannel
+}
+
+fn take_due<T>(pending: &mut Vec<Pending<T>>) -> Vec<Pending<T>> {
+    let now = Instant::now();
+    let (mut due, waiting): (Vec<_>, Vec<_>) = pending.drain(..).partition(|pending| pending.release <= now);
+
+    *pending = waiting;
+    due.sort_by_key(|pending| (pending.release, pending.order));
+
+    due
+}
+
+/// Wraps a server transport, see `LinkConditionerSettings`.
+pub struct ConditionedServerTransport {
+    pub(crate) inner: Box<dyn ServerTransport>,
+    pub(crate) settings: LinkConditionerSettings,
+    links: HashMap<Uuid, (LinkSimulator, LinkSimulator)>,
+    outgoing: Vec<Pending<Uuid>>,
+    incoming: Vec<Pending<Uuid>>,
+    order: u64,
+    routes: SharedMessageRoutes
+}
+
+impl ConditionedServerTransport {
+    pub fn new(inner: Box<dyn ServerTransport>, settings: LinkConditionerSettings) -> Self {
+        let mut transport = ConditionedServerTransport {
+            inner,
+            settings,
+            links: HashMap::new(),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            order: 0,
+            routes: SharedMessageRoutes::default()
+        };
+
+        // Clients accepted before the conditioner was set keep working.
+        for client in transport.inner.clients() {
+            transport.link(&client);
+        }
+
+        transport
+    }
+
+    /// The channels registered in the App, received messages are delayed according to them.
+    pub(crate) fn with_routes(mut self, routes: SharedMessageRoutes) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    fn link(&mut self, client: &Uuid) -> &mut (LinkSimulator, LinkSimulator) {
+        let settings = &self.settings;
+
+        self.links.entry(*client).or_insert_with(|| {
+            let stream = client.as_u64_pair().0;
+
+            (LinkSimulator::new(settings, stream), LinkSimulator::new(settings, !stream))
+        })
+    }
+
+    fn forget(&mut self, client: &Uuid) {
+        self.links.remove(client);
+        self.outgoing.retain(|pending| &pending.target != client);
+        self.incoming.retain(|pending| &pending.target != client);
+    }
+}
+
+impl ServerTransport for ConditionedServerTransport {
+    fn kind(&self) -> ConnectionsType {
+        self.inner.kind()
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.inner.set_runtime(runtime)
+    }
+
+    fn start(&mut self) {
+        self.inner.start()
+    }
+
+    fn state(&self) -> TransportState {
+        self.inner.state()
+    }
+
+    fn update(&mut self) {
+        for pending in take_due(&mut self.outgoing) {
+            self.inner.send(&pending.target, pending.message.as_ref(), pending.channel);
+        }
+
+        self.inner.update()
+    }
+
+    fn poll_accepted(&mut self) -> Vec<Uuid> {
+        let accepted = self.inner.poll_accepted();
+
+        for client in accepted.iter() {
+            self.link(client);
+        }
+
+        accepted
+    }
+
+    fn poll_disconnected(&mut self) -> Vec<Uuid> {
+        let disconnected = self.inner.poll_disconnected();
+
+        for client in disconnected.iter() {
+            self.forget(client);
+        }
+
+        disconnected
+    }
+
+    fn poll_received(&mut self) -> Vec<(Uuid, Box<dyn MessageTrait>)> {
+        for (client, message) in self.inner.poll_received().into_iter().flat_map(|(client, message)| unbatch(message).into_iter().map(move |message| (client, message))) {
+            let channel = self.routes.read().unwrap().channel(message.as_ref());
+
+            for release in self.link(&client).1.schedule(message.as_ref(), channel) {
+                self.order += 1;
+                self.incoming.push(Pending { release, order: self.order, target: client, message: message.clone_message(), channel });
+            }
+        }
+
+        take_due(&mut self.incoming).into_iter().map(|pending| (pending.target, pending.message)).collect()
+    }
+
+    fn send(&mut self, client: &Uuid, message: &dyn MessageTrait, channel: MessageChannel) -> bool {
+        let Some((outgoing_link, _)) = self.links.get_mut(client) else {
+            return false;
+        };
+
+        for release in outgoing_link.schedule(message, channel) {
+            self.order += 1;
+            self.outgoing.push(Pending { release, order: self.order, target: *client, message: message.clone_message(), channel });
+        }
+
+        true
+    }
+
+    fn disconnect_client(&mut self, client: &Uuid) {
+        self.forget(client);
+        self.inner.disconnect_client(client)
+    }
+
+    fn clients(&self) -> Vec<Uuid> {
+        self.inner.clients()
+    }
+
+    fn client_address(&self, client: &Uuid) -> Option<SocketAddr> {
+        self.inner.client_address(client)
+    }
+
+    fn poll_rejected(&mut self) -> Vec<Uuid> {
+        self.inner.poll_rejected()
+    }
+
+    fn queue_depth(&self, client: &Uuid) -> Option<usize> {
+        self.inner.queue_depth(client)
+    }
+
+    fn batches_messages(&self) -> bool {
+        self.inner.batches_messages()
+    }
+
+    fn compression_stats(&self, client: &Uuid) -> Option<CompressionStats> {
+        self.inner.compression_stats(client)
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown()
+    }
+}
+
+/// Wraps a client transport, see `LinkConditionerSettings`.
+pub struct ConditionedClientTransport {
+    pub(crate) inner: Box<dyn ClientTransport>,
+    outgoing_link: LinkSimulator,
+    incoming_link: LinkSimulator,
+    outgoing: Vec<Pending<()>>,
+    incoming: Vec<Pending<()>>,
+    order: u64,
+    routes: SharedMessageRoutes
+}
+
+impl ConditionedClientTransport {
+    pub fn new(inner: Box<dyn ClientTransport>, settings: LinkConditionerSettings) -> Self {
+        ConditionedClientTransport {
+            inner,
+            outgoing_link: LinkSimulator::new(&settings, 1),
+            incoming_link: LinkSimulator::new(&settings, 2),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            order: 0,
+            routes: SharedMessageRoutes::default()
+        }
+    }
+
+    /// The channels registered in the App, received messages are delayed according to them.
+    pub(crate) fn with_routes(mut self, routes: SharedMessageRoutes) -> Self {
+        self.routes = routes;
+        self
+    }
+}
+
+impl ClientTransport for ConditionedClientTransport {
+    fn kind(&self) -> ConnectionsType {
+        self.inner.kind()
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.inner.set_runtime(runtime)
+    }
+
+    fn start(&mut self) {
+        self.inner.start()
+    }
+
+    fn state(&self) -> TransportState {
+        self.inner.state()
+    }
+
+    fn update(&mut self) {
+        for pending in take_due(&mut self.outgoing) {
+            self.inner.send(pending.message.as_ref(), pending.channel);
+        }
+
+        self.inner.update();
+
+        if self.inner.state() != TransportState::Connected {
+            self.outgoing.clear();
+            self.incoming.clear();
+        }
+    }
+
+    fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>> {
+        for message in self.inner.poll_received().into_iter().flat_map(unbatch) {
+            let channel = self.routes.read().unwrap().channel(message.as_ref());
+
+            for release in self.incoming_link.schedule(message.as_ref(), channel) {
+                self.order += 1;
+                self.incoming.push(Pending { release, order: self.order, target: (), message: message.clone_message(), channel });
+            }
+        }
+
+        take_due(&mut self.incoming).into_iter().map(|pending| pending.message).collect()
+    }
+
+    fn send(&mut self, message: &dyn MessageTrait, channel: MessageChannel) -> bool {
+        if self.inner.state() != TransportState::Connected {
+            return false;
+        }
+
+        for release in self.outgoing_link.schedule(message, channel) {
+            self.order += 1;
+            self.outgoing.push(Pending { release, order: self.order, target: (), message: message.clone_message(), channel });
+        }
+
+        true
+    }
+
+    fn queue_depth(&self) -> Option<usize> {
+        self.inner.queue_depth()
+    }
+
+    fn batches_messages(&self) -> bool {
+        self.inner.batches_messages()
+    }
+
+    fn compression_stats(&self) -> Option<CompressionStats> {
+        self.inner.compression_stats()
+    }
+
+    fn cancel(&mut self) {
+        self.outgoing.clear();
+        self.incoming.clear();
+        self.inner.cancel()
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connections::heartbeat::HeartbeatPing;
+    use crate::systems::batching::{batch_messages, DEFAULT_BATCH_SIZE};
+    use crate::systems::messaging::{deserialize_message, MessageRoutes};
+
+    /// Hands out what it was given as received messages.
+    struct Replay(Vec<Box<dyn MessageTrait>>);
+
+    impl ClientTransport for Replay {
+        fn kind(&self) -> ConnectionsType {
+            ConnectionsType::Memory
+        }
+
+        fn start(&mut self) {}
+
+        fn state(&self) -> TransportState {
+            TransportState::Connected
+        }
+
+        fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>> {
+            std::mem::take(&mut self.0)
+        }
+
+        fn send(&mut self, _message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+            true
+        }
+
+        fn cancel(&mut self) {}
+
+        fn shutdown(&mut self) {}
+    }
+
+    fn ping(sent_at: u64) -> HeartbeatPing {
+        HeartbeatPing { sent_at }
+    }
+
+    /// A batch of pings as it comes off the wire, with the channel it was sent on gone.
+    fn received_batch(count: u64) -> Box<dyn MessageTrait> {
+        let messages = (0..count).map(|sent_at| Box::new(ping(sent_at)) as Box<dyn MessageTrait>).collect();
+        let batched = batch_messages(messages, DEFAULT_BATCH_SIZE, &MessageRoutes::default());
+
+        assert_eq!(batched.len(), 1);
+
+        deserialize_message(&encode_message(batched[0].as_ref()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn same_seed_loses_the_same_messages() {
+        let settings = LinkConditionerSettings::new(Duration::ZERO, Duration::ZERO, 0.5).with_seed(7);
+        let losses = || {
+            let mut link = LinkSimulator::new(&settings, 1);
+
+            (0..1000).map(|sent_at| link.schedule(&ping(sent_at), MessageChannel::Unreliable).is_empty()).collect::<Vec<_>>()
+        };
+
+        let lost = losses();
+
+        assert_eq!(lost, losses());
+        assert!((400..600).contains(&lost.iter().filter(|lost| **lost).count()));
+    }
+
+    #[test]
+    fn reliable_messages_are_late_instead_of_lost() {
+        let latency = Duration::from_millis(50);
+        let mut link = LinkSimulator::new(&LinkConditionerSettings::new(latency, Duration::ZERO, 1.0), 1);
+        let before = Instant::now();
+
+        for channel in [MessageChannel::ReliableOrdered, MessageChannel::ReliableUnordered] {
+            let releases = link.schedule(&ping(0), channel);
+
+            assert_eq!(releases.len(), 1);
+            assert!(releases[0] >= before + latency * 3);
+        }
+
+        assert!(link.schedule(&ping(0), MessageChannel::Unreliable).is_empty());
+    }
+
+    #[test]
+    fn delays_stay_within_latency_and_jitter() {
+        let latency = Duration::from_millis(100);
+        let jitter = Duration::from_millis(20);
+        let mut link = LinkSimulator::new(&LinkConditionerSettings::new(latency, jitter, 0.0).with_seed(3), 1);
+        let mut last_release = Instant::now();
+
+        for sent_at in 0..100 {
+            let before = Instant::now();
+            let release = link.schedule(&ping(sent_at), MessageChannel::ReliableOrdered)[0];
+
+            assert!(release >= before + latency);
+            assert!(release <= Instant::now() + latency + jitter);
+            assert!(release >= last_release);
+
+            last_release = release;
+        }
+    }
+
+    #[test]
+    fn bandwidth_spaces_messages_out() {
+        let size = encode_message(&ping(0)).unwrap().len();
+        // Each ping takes 125 ms to go through.
+        let mut link = LinkSimulator::new(&LinkConditionerSettings::default().with_bandwidth(size as u32 * 8), 1);
+        let before = Instant::now();
+        let releases: Vec<_> = (0..4).map(|_| link.schedule(&ping(0), MessageChannel::Unreliable)[0]).collect();
+        let after = Instant::now();
+
+        for (i, release) in releases.into_iter().enumerate() {
+            let transmission = Duration::from_millis(125) * (i as u32 + 1);
+
+            assert!(release >= before + transmission);
+            assert!(release <= after + transmission);
+        }
+    }
+
+    #[test]
+    fn batched_messages_keep_their_channel() {
+        let mut lossy = ConditionedClientTransport::new(Box::new(Replay(vec![received_batch(20)])), LinkConditionerSettings::new(Duration::ZERO, Duration::ZERO, 1.0));
+
+        assert!(lossy.poll_received().is_empty());
+        assert!(lossy.incoming.is_empty());
+
+        let mut clean = ConditionedClientTransport::new(Box::new(Replay(vec![received_batch(20)])), LinkConditionerSettings::default());
+        let received: Vec<_> = clean.poll_received().iter()
+            .map(|message| message.as_any().downcast_ref::<HeartbeatPing>().unwrap().sent_at)
+            .collect();
+
+        assert_eq!(received, (0..20).collect::<Vec<_>>());
+    }
+}
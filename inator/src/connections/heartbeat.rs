@@ -0,0 +1,200 @@
+use std::time::Duration;
+use bevy::platform::time::Instant;
+use serde::{Deserialize, Serialize};
+use message_derive::Message;
+use crate::connections::MessageChannel;
+use crate::systems::messaging::MessageTrait;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatSettings {
+    pub(crate) interval: Duration,
+    pub(crate) timeout: Duration
+}
+
+/// Smoothed like TCP does, `rtt` with a gain of 1/8 and `jitter` with a gain of 1/4.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RttStats {
+    pub rtt: Duration,
+    pub jitter: Duration
+}
+
+#[derive(Serialize, Deserialize, Message, Clone)]
+#[message(channel = Unreliable)]
+pub(crate) struct HeartbeatPing {
+    pub sent_at: u64
+}
+
+#[derive(Serialize, Deserialize, Message, Clone)]
+#[message(channel = Unreliable)]
+pub(crate) struct HeartbeatPong {
+    pub sent_at: u64
+}
+
+/// Heartbeat state of one link, on the server there is one per client.
+pub(crate) struct LinkHealth {
+    epoch: Instant,
+    last_received: Instant,
+    last_ping: Instant,
+    rtt: Option<RttStats>
+}
+
+impl Default for HeartbeatSettings {
+    fn default() -> Self {
+        HeartbeatSettings {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(10)
+        }
+    }
+}
+
+impl HeartbeatSettings {
+    /// A link that received nothing, pings included, for `timeout` is disconnected.
+    pub fn new(interval: Duration, timeout: Duration) -> Self {
+        Self {
+            interval,
+            timeout
+        }
+    }
+}
+
+impl RttStats {
+    fn update(&mut self, sample: Duration) {
+        let deviation = sample.abs_diff(self.rtt);
+
+        self.jitter = (self.jitter * 3 + deviation) / 4;
+        self.rtt = (self.rtt * 7 + sample) / 8;
+    }
+}
+
+impl LinkHealth {
+    pub(crate) fn new() -> Self {
+        LinkHealth::started_at(Instant::now())
+    }
+
+    pub(crate) fn started_at(now: Instant) -> Self {
+        LinkHealth {
+            epoch: now,
+            last_received: now,
+            last_ping: now,
+            rtt: None
+        }
+    }
+
+    pub(crate) fn rtt(&self) -> Option<RttStats> {
+        self.rtt
+    }
+
+    pub(crate) fn received(&mut self) {
+        self.last_received = Instant::now();
+    }
+
+    pub(crate) fn timed_out(&self, settings: &HeartbeatSettings) -> bool {
+        self.last_received.elapsed() >= settings.timeout
+    }
+
+    /// Returns the ping to send when the interval has passed.
+    pub(crate) fn ping(&mut self, settings: &HeartbeatSettings) -> Option<HeartbeatPing> {
+        if self.last_ping.elapsed() < settings.interval {
+            return None;
+        }
+
+        self.last_ping = Instant::now();
+
+        Some(HeartbeatPing {
+            sent_at: self.epoch.elapsed().as_micros() as u64
+        })
+    }
+
+    pub(crate) fn pong_received(&mut self, pong: &HeartbeatPong) {
+        let now = self.epoch.elapsed().as_micros() as u64;
+
+        if pong.sent_at > now {
+            return;
+        }
+
+        let sample = Duration::from_micros(now - pong.sent_at);
+
+        match self.rtt.as_mut() {
+            Some(rtt) => rtt.update(sample),
+            None => {
+                self.rtt = Some(RttStats {
+                    rtt: sample,
+                    jitter: sample / 2
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds_ago(seconds: u64) -> Instant {
+        Instant::now() - Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn silent_link_times_out() {
+        let settings = HeartbeatSettings::new(Duration::from_secs(1), Duration::from_secs(10));
+
+        assert!(!LinkHealth::started_at(seconds_ago(9)).timed_out(&settings));
+
+        let mut link = LinkHealth::started_at(seconds_ago(10));
+
+        assert!(link.timed_out(&settings));
+
+        link.received();
+        assert!(!link.timed_out(&settings));
+    }
+
+    #[test]
+    fn pings_wait_for_the_interval() {
+        let settings = HeartbeatSettings::new(Duration::from_secs(1), Duration::from_secs(10));
+
+        assert!(LinkHealth::started_at(Instant::now()).ping(&settings).is_none());
+
+        let mut link = LinkHealth::started_at(seconds_ago(2));
+        let ping = link.ping(&settings).unwrap();
+
+        assert!(ping.sent_at >= 2_000_000);
+        assert!(link.ping(&settings).is_none());
+    }
+
+    #[test]
+    fn first_pong_sets_the_rtt() {
+        let mut link = LinkHealth::started_at(seconds_ago(1));
+
+        link.pong_received(&HeartbeatPong { sent_at: 800_000 });
+
+        let stats = link.rtt().unwrap();
+
+        assert!(stats.rtt >= Duration::from_millis(200) && stats.rtt < Duration::from_millis(300));
+        assert_eq!(stats.jitter, stats.rtt / 2);
+    }
+
+    #[test]
+    fn pongs_from_the_future_are_ignored() {
+        let mut link = LinkHealth::started_at(Instant::now());
+
+        link.pong_received(&HeartbeatPong { sent_at: u64::MAX });
+
+        assert_eq!(link.rtt(), None);
+    }
+
+    #[test]
+    fn rtt_is_smoothed() {
+        let mut stats = RttStats {
+            rtt: Duration::from_millis(100),
+            jitter: Duration::ZERO
+        };
+
+        stats.update(Duration::from_millis(180));
+
+        assert_eq!(stats, RttStats { rtt: Duration::from_millis(110), jitter: Duration::from_millis(20) });
+
+        stats.update(Duration::from_millis(110));
+
+        assert_eq!(stats, RttStats { rtt: Duration::from_millis(110), jitter: Duration::from_millis(15) });
+    }
+}
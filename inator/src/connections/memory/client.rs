@@ -0,0 +1,132 @@
+use crate::connections::{Connection, ConnectionsType, MessageChannel};
+use crate::connections::memory::connection::MemoryConnection;
+use crate::connections::memory::server::MemoryConnector;
+use crate::connections::transport::{ClientTransport, TransportState};
+use crate::systems::messaging::MessageTrait;
+
+pub struct ClientMemorySettings {
+    pub(crate) connector: MemoryConnector
+}
+
+pub struct ClientMemoryConnection {
+    pub(crate) settings: ClientMemorySettings,
+    pub(crate) name: &'static str,
+    pub(crate) started: bool,
+    pub(crate) local_memory_connection: Option<MemoryConnection>
+}
+
+impl ClientMemorySettings {
+    pub fn new(connector: MemoryConnector) -> Self {
+        Self {
+            connector
+        }
+    }
+}
+
+impl ClientMemoryConnection {
+    pub fn new(settings: ClientMemorySettings, name: &'static str) -> ClientMemoryConnection {
+        ClientMemoryConnection {
+            settings,
+            name,
+            started: false,
+            local_memory_connection: None
+        }
+    }
+}
+
+impl Connection for ClientMemoryConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        let (client_half, server_half) = MemoryConnection::pair(self.name);
+
+        if self.settings.connector.client_connected_sender.send(server_half).is_err() {
+            println!("Failed to connect to server: memory server is closed");
+            return;
+        }
+
+        self.started = true;
+        self.local_memory_connection = Some(client_half);
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(local_memory_connection) = self.local_memory_connection.take() {
+            drop(local_memory_connection);
+        }
+
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(local_memory_connection) = self.local_memory_connection.take() {
+            drop(local_memory_connection);
+        }
+    }
+}
+
+impl ClientTransport for ClientMemoryConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Memory
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.local_memory_connection.is_some() {
+            TransportState::Connected
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn batches_messages(&self) -> bool {
+        false
+    }
+
+    fn update(&mut self) {
+        let connection_down = match self.local_memory_connection.as_mut() {
+            Some(local_memory_connection) => local_memory_connection.connection_down_receiver.try_recv().is_ok(),
+            None => false
+        };
+
+        if connection_down {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>> {
+        let mut received = Vec::new();
+
+        if let Some(local_memory_connection) = self.local_memory_connection.as_mut() {
+            while let Ok(message) = local_memory_connection.message_received_receiver.try_recv() {
+                received.push(message);
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+        match self.local_memory_connection.as_mut() {
+            Some(local_memory_connection) => {
+                local_memory_connection.send_message(message);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.cancel_connection()
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
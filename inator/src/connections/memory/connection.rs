@@ -0,0 +1,65 @@
+use std::sync::Arc;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+pub struct MemoryConnection {
+    pub connection_name: &'static str,
+    pub network_side: NetworkSide,
+    pub uuid: Option<Uuid>,
+    pub connection_down_sender: Arc<UnboundedSender<()>>,
+    pub connection_down_receiver: UnboundedReceiver<()>,
+    pub message_received_receiver: UnboundedReceiver<Box<dyn MessageTrait>>,
+    pub(crate) peer_message_sender: UnboundedSender<Box<dyn MessageTrait>>,
+    pub(crate) peer_down_sender: Arc<UnboundedSender<()>>
+}
+
+impl Drop for MemoryConnection {
+    fn drop(&mut self) {
+        let _ = self.peer_down_sender.send(());
+    }
+}
+
+impl MemoryConnection {
+    /// Creates both ends of an in-process link, the client half first.
+    pub(crate) fn pair(connection_name: &'static str) -> (MemoryConnection, MemoryConnection) {
+        let (client_down_sender, client_down_receiver) = unbounded_channel::<()>();
+        let (server_down_sender, server_down_receiver) = unbounded_channel::<()>();
+        let (to_client_sender, to_client_receiver) = unbounded_channel::<Box<dyn MessageTrait>>();
+        let (to_server_sender, to_server_receiver) = unbounded_channel::<Box<dyn MessageTrait>>();
+        let client_down_sender = Arc::new(client_down_sender);
+        let server_down_sender = Arc::new(server_down_sender);
+
+        let client_half = MemoryConnection {
+            connection_name,
+            network_side: NetworkSide::Client,
+            uuid: None,
+            connection_down_sender: Arc::clone(&client_down_sender),
+            connection_down_receiver: client_down_receiver,
+            message_received_receiver: to_client_receiver,
+            peer_message_sender: to_server_sender,
+            peer_down_sender: Arc::clone(&server_down_sender)
+        };
+
+        let server_half = MemoryConnection {
+            connection_name,
+            network_side: NetworkSide::Server,
+            uuid: Some(Uuid::new_v4()),
+            connection_down_sender: server_down_sender,
+            connection_down_receiver: server_down_receiver,
+            message_received_receiver: to_server_receiver,
+            peer_message_sender: to_client_sender,
+            peer_down_sender: client_down_sender
+        };
+
+        (client_half, server_half)
+    }
+
+    pub fn send_message(&mut self, message: &dyn MessageTrait) {
+        if self.peer_message_sender.send(message.clone_message()).is_err() {
+            eprintln!("Memory peer is closed");
+            eprintln!("From {:?}", self.network_side);
+        }
+    }
+}
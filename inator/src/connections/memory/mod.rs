@@ -0,0 +1,3 @@
+﻿pub mod server;
+pub mod client;
+pub mod connection;
\ No newline at end of file
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+use crate::connections::{Connection, ConnectionsType, MessageChannel};
+use crate::connections::memory::connection::MemoryConnection;
+use crate::connections::transport::{ServerTransport, TransportState};
+use crate::systems::messaging::MessageTrait;
+
+#[derive(Default)]
+pub struct ServerMemorySettings {
+    pub(crate) max_connections: usize
+}
+
+/// Handle a client in the same process uses to reach a memory server,
+/// obtained from `ServerConnections::memory_connector`.
+#[derive(Clone)]
+pub struct MemoryConnector {
+    pub(crate) client_connected_sender: Arc<UnboundedSender<MemoryConnection>>
+}
+
+pub struct ServerMemoryConnection {
+    pub(crate) settings: ServerMemorySettings,
+    pub(crate) name: &'static str,
+    pub(crate) started: bool,
+    pub(crate) client_connected_sender: Arc<UnboundedSender<MemoryConnection>>,
+    pub(crate) client_connected_receiver: UnboundedReceiver<MemoryConnection>,
+    pub(crate) connections: HashMap<Uuid, MemoryConnection>
+}
+
+impl ServerMemorySettings {
+    pub fn new(max_connections: usize) -> Self {
+        Self {
+            max_connections
+        }
+    }
+}
+
+impl ServerMemoryConnection {
+    pub fn new(settings: ServerMemorySettings, name: &'static str) -> ServerMemoryConnection {
+        let (client_connected_sender, client_connected_receiver) = unbounded_channel::<MemoryConnection>();
+
+        ServerMemoryConnection {
+            settings,
+            name,
+            started: false,
+            client_connected_sender: Arc::new(client_connected_sender),
+            client_connected_receiver,
+            connections: HashMap::new()
+        }
+    }
+
+    pub fn connector(&self) -> MemoryConnector {
+        MemoryConnector {
+            client_connected_sender: Arc::clone(&self.client_connected_sender)
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.settings.max_connections > 0 && self.connections.len() >= self.settings.max_connections
+    }
+}
+
+impl Connection for ServerMemoryConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        self.started = true;
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        self.connections.clear();
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        self.connections.clear();
+    }
+}
+
+impl ServerTransport for ServerMemoryConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Memory
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.started {
+            TransportState::Connected
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn batches_messages(&self) -> bool {
+        false
+    }
+
+    fn poll_accepted(&mut self) -> Vec<Uuid> {
+        let mut accepted = Vec::new();
+
+        while let Ok(memory_connection) = self.client_connected_receiver.try_recv() {
+            if self.is_full() {
+                println!("Connection {} is full, rejecting memory connection", self.name);
+                continue
+            }
+
+            let current_uuid = memory_connection.uuid.unwrap();
+
+            self.connections.insert(current_uuid,memory_connection);
+            accepted.push(current_uuid);
+        }
+
+        accepted
+    }
+
+    fn poll_disconnected(&mut self) -> Vec<Uuid> {
+        let mut disconnected = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            if client_connection.connection_down_receiver.try_recv().is_ok() {
+                disconnected.push(*uuid);
+            }
+        }
+
+        for uuid in disconnected.iter() {
+            self.connections.remove(uuid);
+        }
+
+        disconnected
+    }
+
+    fn poll_received(&mut self) -> Vec<(Uuid, Box<dyn MessageTrait>)> {
+        let mut received = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            while let Ok(message) = client_connection.message_received_receiver.try_recv() {
+                received.push((*uuid, message));
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, client: &Uuid, message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+        match self.connections.get_mut(client) {
+            Some(client_connection) => {
+                client_connection.send_message(message);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn disconnect_client(&mut self, client: &Uuid) {
+        self.connections.remove(client);
+    }
+
+    fn clients(&self) -> Vec<Uuid> {
+        self.connections.keys().copied().collect()
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
@@ -1,21 +1,94 @@
-﻿use std::collections::HashMap;
+﻿use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::IpAddr;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::platform::time::Instant;
 use bevy::log::warn;
-use bevy::prelude::Resource;
+use bevy::prelude::{Resource, States};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::auth::{Authenticator, PendingAuth};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::conditioner::{ConditionedClientTransport, ConditionedServerTransport, LinkConditionerSettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::memory::client::{ClientMemoryConnection, ClientMemorySettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::memory::server::{MemoryConnector, ServerMemoryConnection, ServerMemorySettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::quic::client::{ClientQuicConnection, ClientQuicSettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::quic::server::{ServerQuicConnection, ServerQuicSettings};
+#[cfg(not(target_arch = "wasm32"))]
 use crate::connections::tcp::client::{ClientTcpConnection, ClientTcpSettings};
+#[cfg(not(target_arch = "wasm32"))]
 use crate::connections::tcp::server::{ServerTcpConnection, ServerTcpSettings};
-use crate::systems::messaging::MessageTrait;
-
+use crate::connections::compression::CompressionStats;
+use crate::connections::heartbeat::{HeartbeatSettings, LinkHealth, RttStats};
+use crate::connections::reconnect::{ReconnectSettings, ReconnectState};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::session::{BanList, ServerSessions, DISCONNECT_LINGER};
+use crate::connections::session::ResumeToken;
+use crate::connections::transport::{ClientTransport, ServerTransport, TransportState};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::udp::client::{ClientUdpConnection, ClientUdpSettings};
+#[cfg(unix)]
+use crate::connections::unix::client::{ClientUnixConnection, ClientUnixSettings};
+#[cfg(unix)]
+use crate::connections::unix::server::{ServerUnixConnection, ServerUnixSettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::udp::server::{ServerUdpConnection, ServerUdpSettings};
+use crate::connections::websocket::client::{ClientWebSocketConnection, ClientWebSocketSettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::websocket::server::{ServerWebSocketConnection, ServerWebSocketSettings};
+use crate::plugins::DisconnectReason;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::plugins::DisconnectMessage;
+use crate::systems::batching::{batch_messages, DEFAULT_BATCH_SIZE};
+use crate::systems::messaging::{MessageTrait, SharedMessageRoutes};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod auth;
+pub mod compression;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod conditioner;
+pub mod heartbeat;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod memory;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod quic;
+pub mod reconnect;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod runtime;
+pub mod session;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod tcp;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tls;
+pub mod transport;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod udp;
+#[cfg(unix)]
+pub mod unix;
+pub mod websocket;
 
 type ConnectMap<T> = HashMap<String,T>;
 
+/// How long servers wait before trying to bind their address again.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const BIND_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The routes are the message channels and directions registered in the App, see `MessageDispatchers`.
 #[derive(Resource)]
-pub struct ClientConnections(pub ConnectMap<ClientConnectionType>);
+pub struct ClientConnections(pub ConnectMap<ClientConnection>, pub(crate) SharedMessageRoutes);
 
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Resource)]
-pub struct ServerConnections(pub ConnectMap<ServerConnectionType>);
+pub struct ServerConnections(pub ConnectMap<ServerConnection>, pub(crate) SharedMessageRoutes);
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum OrderOptions{
@@ -42,7 +115,16 @@ pub enum BytesOptions {
 }
 
 
-#[derive(Debug)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+pub enum MessageChannel {
+    #[default]
+    ReliableOrdered,
+    ReliableUnordered,
+    Unreliable,
+    UnreliableSequenced
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum ReadValue {
     U8(u8),
     U16(u16),
@@ -60,17 +142,63 @@ pub enum ReadValue {
     F64(f64),
 }
 
-#[derive(Serialize, Deserialize)]
+/// Where a client connection is, `Handshaking` means the link is up but the server did not send the uuid yet.
+/// Also usable as a Bevy state through `ClientStatePlugin`.
+#[derive(States, Eq, PartialEq, Hash, Debug, Clone, Copy, Default)]
+pub enum ClientConnectionState {
+    #[default]
+    Disconnected,
+    Connecting,
+    Handshaking,
+    Connected
+}
+
+/// `Custom` is reported by transports that live outside of this crate.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
 pub enum ConnectionsType{
-    Tcp
+    Tcp,
+    Udp,
+    WebSocket,
+    Quic,
+    Memory,
+    Unix,
+    Custom(String)
 }
 
-pub enum ServerConnectionType{
-    Tcp(ServerTcpConnection)
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ServerConnection {
+    pub(crate) name: &'static str,
+    pub(crate) transport: Box<dyn ServerTransport>,
+    pub(crate) heartbeat: HeartbeatSettings,
+    pub(crate) links: HashMap<Uuid, LinkHealth>,
+    pub(crate) sessions: ServerSessions,
+    pub(crate) bans: BanList,
+    pub(crate) closing: HashMap<Uuid, Instant>,
+    pub(crate) closed_sessions: Vec<Uuid>,
+    pub(crate) authenticator: Authenticator,
+    pub(crate) pending_auth: HashMap<Uuid, PendingAuth>,
+    pub(crate) protocol_violations: HashMap<Uuid, u32>,
+    pub(crate) rejected_frames: HashMap<Uuid, u32>,
+    pub(crate) batch_size: usize,
+    pub(crate) outgoing: HashMap<Uuid, Vec<Box<dyn MessageTrait>>>,
+    pub(crate) routes: SharedMessageRoutes
 }
 
-pub enum ClientConnectionType{
-    Tcp(ClientTcpConnection)
+pub struct ClientConnection {
+    pub(crate) name: &'static str,
+    pub(crate) uuid: Option<Uuid>,
+    pub(crate) transport: Box<dyn ClientTransport>,
+    pub(crate) heartbeat: HeartbeatSettings,
+    pub(crate) link: Option<LinkHealth>,
+    pub(crate) reconnect: ReconnectSettings,
+    pub(crate) reconnect_state: ReconnectState,
+    pub(crate) session: Option<ResumeToken>,
+    pub(crate) disconnect_reason: Option<DisconnectReason>,
+    pub(crate) credentials: Vec<u8>,
+    pub(crate) protocol_violations: u32,
+    pub(crate) batch_size: usize,
+    pub(crate) outgoing: Vec<Box<dyn MessageTrait>>,
+    pub(crate) routes: SharedMessageRoutes
 }
 
 pub trait Connection {
@@ -92,24 +220,251 @@ impl Default for BytesOptions{
     }
 }
 
+impl MessageChannel {
+    pub fn is_reliable(&self) -> bool {
+        matches!(self, MessageChannel::ReliableOrdered | MessageChannel::ReliableUnordered)
+    }
+
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            MessageChannel::ReliableOrdered => 0,
+            MessageChannel::ReliableUnordered => 1,
+            MessageChannel::Unreliable => 2,
+            MessageChannel::UnreliableSequenced => 3,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<MessageChannel> {
+        match byte {
+            0 => Some(MessageChannel::ReliableOrdered),
+            1 => Some(MessageChannel::ReliableUnordered),
+            2 => Some(MessageChannel::Unreliable),
+            3 => Some(MessageChannel::UnreliableSequenced),
+            _ => None
+        }
+    }
+}
+
 impl Default for OrderOptions{
     fn default() -> OrderOptions{
         OrderOptions::LittleEndian
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl ServerConnection {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn kind(&self) -> ConnectionsType {
+        self.transport.kind()
+    }
+
+    pub fn state(&self) -> TransportState {
+        self.transport.state()
+    }
+
+    pub fn clients(&self) -> Vec<Uuid> {
+        self.sessions.clients()
+    }
+
+    pub fn rtt(&self, client: &Uuid) -> Option<RttStats> {
+        self.sessions.transport_of(client)
+            .and_then(|transport_id| self.links.get(&transport_id))
+            .and_then(|link| link.rtt())
+    }
+
+    /// Messages this client sent that only the server may send, see `MessageDirection`.
+    pub fn protocol_violations(&self, client: &Uuid) -> u32 {
+        self.protocol_violations.get(client).copied().unwrap_or(0)
+    }
+
+    /// Messages waiting to be written to this client, `None` when the transport has no send queue.
+    pub fn queue_depth(&self, client: &Uuid) -> Option<usize> {
+        self.sessions.transport_of(client)
+            .and_then(|transport_id| self.transport.queue_depth(&transport_id))
+    }
+
+    /// Frame bytes exchanged with this client before and after compression, `None` when the
+    /// transport does not compress.
+    pub fn compression_stats(&self, client: &Uuid) -> Option<CompressionStats> {
+        self.sessions.transport_of(client)
+            .and_then(|transport_id| self.transport.compression_stats(&transport_id))
+    }
+
+    /// Frames from this client that had an invalid or oversized size prefix.
+    pub fn rejected_frames(&self, client: &Uuid) -> u32 {
+        self.rejected_frames.get(client).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn forget_counters(&mut self, client: &Uuid) {
+        self.protocol_violations.remove(client);
+        self.rejected_frames.remove(client);
+    }
+
+    /// Queues the message until `flush`, unless batching is off.
+    pub(crate) fn send(&mut self, client: &Uuid, message: &dyn MessageTrait) -> bool {
+        let Some(transport_id) = self.sessions.transport_of(client) else {
+            return false;
+        };
+
+        if self.batch_size == 0 || !self.transport.batches_messages() {
+            return self.transmit(&transport_id, message);
+        }
+
+        self.outgoing.entry(transport_id).or_default().push(message.clone_message());
+        true
+    }
+
+    /// Hands the message to the transport right away, on the channel registered for its type.
+    pub(crate) fn transmit(&mut self, transport_id: &Uuid, message: &dyn MessageTrait) -> bool {
+        let channel = self.routes.read().unwrap().channel(message);
+
+        self.transport.send(transport_id, message, channel)
+    }
+
+    pub(crate) fn flush(&mut self) {
+        for (transport_id, messages) in std::mem::take(&mut self.outgoing) {
+            self.flush_link(transport_id, messages);
+        }
+    }
+
+    fn flush_link(&mut self, transport_id: Uuid, messages: Vec<Box<dyn MessageTrait>>) {
+        let batched = batch_messages(messages, self.batch_size, &self.routes.read().unwrap());
+
+        for message in batched {
+            self.transmit(&transport_id, message.as_ref());
+        }
+    }
+
+    /// Sends the reason and closes the link once `DISCONNECT_LINGER` has passed.
+    pub(crate) fn close_link(&mut self, transport_id: Uuid, reason: DisconnectReason) {
+        if let Some(messages) = self.outgoing.remove(&transport_id) {
+            self.flush_link(transport_id, messages);
+        }
+
+        self.transmit(&transport_id, &DisconnectMessage {
+            reason
+        });
+
+        self.links.remove(&transport_id);
+        self.closing.insert(transport_id, Instant::now() + DISCONNECT_LINGER);
+    }
+
+    pub(crate) fn is_banned(&self, transport_id: &Uuid) -> bool {
+        self.transport.client_address(transport_id)
+            .is_some_and(|address| self.bans.addresses.contains(&address.ip()))
+    }
+
+    pub fn transport_mut(&mut self) -> &mut dyn ServerTransport {
+        self.transport.as_mut()
+    }
+}
+
+impl ClientConnection {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The id the server gave to this client, known once the server's `ConnectedMessage` arrived.
+    pub fn uuid(&self) -> Option<Uuid> {
+        self.uuid
+    }
+
+    pub fn kind(&self) -> ConnectionsType {
+        self.transport.kind()
+    }
+
+    pub fn state(&self) -> TransportState {
+        self.transport.state()
+    }
+
+    pub fn rtt(&self) -> Option<RttStats> {
+        self.link.as_ref().and_then(|link| link.rtt())
+    }
+
+    /// Queues the message until `flush`, unless batching is off. Returns false without a uuid.
+    pub(crate) fn send(&mut self, message: &dyn MessageTrait) -> bool {
+        if self.uuid.is_none() {
+            return false;
+        }
+
+        if self.batch_size == 0 || !self.transport.batches_messages() {
+            return self.transmit(message);
+        }
+
+        self.outgoing.push(message.clone_message());
+        true
+    }
+
+    /// Hands the message to the transport right away, on the channel registered for its type.
+    pub(crate) fn transmit(&mut self, message: &dyn MessageTrait) -> bool {
+        let channel = self.routes.read().unwrap().channel(message);
+
+        self.transport.send(message, channel)
+    }
+
+    pub(crate) fn flush(&mut self) {
+        let batched = batch_messages(std::mem::take(&mut self.outgoing), self.batch_size, &self.routes.read().unwrap());
+
+        for message in batched {
+            self.transmit(message.as_ref());
+        }
+    }
+
+    /// Messages waiting to be written to the server, `None` when the transport has no send queue.
+    pub fn queue_depth(&self) -> Option<usize> {
+        self.transport.queue_depth()
+    }
+
+    /// Frame bytes exchanged with the server before and after compression, `None` when the
+    /// transport does not compress.
+    pub fn compression_stats(&self) -> Option<CompressionStats> {
+        self.transport.compression_stats()
+    }
+
+    /// Messages the server sent that only clients may send, see `MessageDirection`.
+    pub fn protocol_violations(&self) -> u32 {
+        self.protocol_violations
+    }
+
+    /// A connection that gave up stays `Disconnected` until `ClientConnections::reconnect`,
+    /// otherwise it is retrying and reported as `Connecting`.
+    pub fn connection_state(&self) -> ClientConnectionState {
+        if self.uuid.is_some() {
+            ClientConnectionState::Connected
+        } else if self.transport.state() == TransportState::Connected {
+            ClientConnectionState::Handshaking
+        } else if self.reconnect_state.gave_up() {
+            ClientConnectionState::Disconnected
+        } else {
+            ClientConnectionState::Connecting
+        }
+    }
+
+    /// Connection attempts made since the last successful one.
+    pub fn attempts(&self) -> u32 {
+        self.reconnect_state.attempts()
+    }
+
+    pub fn gave_up(&self) -> bool {
+        self.reconnect_state.gave_up()
+    }
+
+    pub fn transport_mut(&mut self) -> &mut dyn ClientTransport {
+        self.transport.as_mut()
+    }
+}
+
 impl Connections for ClientConnections {
     fn new() -> ClientConnections {
-        ClientConnections(HashMap::new())
+        ClientConnections(HashMap::new(), SharedMessageRoutes::default())
     }
 
     fn remove_connection(&mut self, name: &str) {
-        if let Some(connection) = self.0.remove(name) {
-            match connection {
-                ClientConnectionType::Tcp(mut connection) => {
-                    connection.disconnect();
-                }
-            }
+        if let Some(mut connection) = self.0.remove(name) {
+            connection.transport.shutdown();
         }
     }
 
@@ -122,18 +477,15 @@ impl Connections for ClientConnections {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Connections for ServerConnections {
     fn new() -> ServerConnections {
-        ServerConnections(HashMap::new())
+        ServerConnections(HashMap::new(), SharedMessageRoutes::default())
     }
 
     fn remove_connection(&mut self, name: &str) {
-        if let Some(connection) = self.0.remove(name) {
-            match connection {
-                ServerConnectionType::Tcp(mut connection) => {
-                    connection.disconnect();
-                }
-            }
+        if let Some(mut connection) = self.0.remove(name) {
+            connection.transport.shutdown();
         }
     }
     fn is_connection_open(&self, name: &String) -> bool {
@@ -146,7 +498,7 @@ impl Connections for ServerConnections {
 }
 
 impl ClientConnections {
-    pub fn new_client_tcp_connection(&mut self, settings: ClientTcpSettings, name: &'static str) {
+    pub fn new_client_connection<T: ClientTransport>(&mut self, transport: T, name: &'static str) {
         if self.0.contains_key(name) {
             warn!("You already have a connection with this name");
             return;
@@ -160,21 +512,140 @@ impl ClientConnections {
             }
         };
 
-        self.0.insert(parsed_name, ClientConnectionType::Tcp(ClientTcpConnection::new(settings, name)));
+        self.0.insert(parsed_name, ClientConnection {
+            name,
+            uuid: None,
+            transport: Box::new(transport),
+            heartbeat: HeartbeatSettings::default(),
+            link: None,
+            reconnect: ReconnectSettings::default(),
+            reconnect_state: ReconnectState::default(),
+            session: None,
+            disconnect_reason: None,
+            credentials: Vec::new(),
+            protocol_violations: 0,
+            batch_size: DEFAULT_BATCH_SIZE,
+            outgoing: Vec::new(),
+            routes: Arc::clone(&self.1)
+        });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_client_tcp_connection(&mut self, settings: ClientTcpSettings, name: &'static str) {
+        self.new_client_connection(ClientTcpConnection::new(settings, name), name);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_client_udp_connection(&mut self, settings: ClientUdpSettings, name: &'static str) {
+        self.new_client_connection(ClientUdpConnection::new(settings, name), name);
+    }
+
+    pub fn new_client_websocket_connection(&mut self, settings: ClientWebSocketSettings, name: &'static str) {
+        self.new_client_connection(ClientWebSocketConnection::new(settings, name), name);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_client_quic_connection(&mut self, settings: ClientQuicSettings, name: &'static str) {
+        self.new_client_connection(ClientQuicConnection::new(settings, name), name);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_client_memory_connection(&mut self, settings: ClientMemorySettings, name: &'static str) {
+        self.new_client_connection(ClientMemoryConnection::new(settings, name), name);
+    }
+
+    #[cfg(unix)]
+    pub fn new_client_unix_connection(&mut self, settings: ClientUnixSettings, name: &'static str) {
+        self.new_client_connection(ClientUnixConnection::new(settings, name), name);
+    }
+
+    /// Runs the connection through a link conditioner, to test it under latency and loss.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_link_conditioner(&mut self, name: &str, settings: LinkConditionerSettings) {
+        let Some(mut connection) = self.0.remove(name) else {
+            warn!("Invalid connection");
+            return;
+        };
+
+        connection.transport = Box::new(ConditionedClientTransport::new(connection.transport, settings).with_routes(Arc::clone(&self.1)));
+
+        self.0.insert(name.to_string(), connection);
+    }
+
+    pub fn set_heartbeat(&mut self, name: &str, settings: HeartbeatSettings) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.heartbeat = settings,
+            None => warn!("Invalid connection")
+        }
+    }
+
+    pub fn rtt(&self, name: &str) -> Option<RttStats> {
+        self.0.get(name).and_then(|connection| connection.rtt())
+    }
+
+    /// The server gets it as `MessageReceivedFromClient<T>` once `T` is registered on its side.
+    /// Messages are only sent after the server gave this client its uuid.
+    pub fn send_message(&mut self, name: &str, message: &dyn MessageTrait) {
+        let connection = self.0.get_mut(name);
+
+        if let Some(connection) = connection {
+            if !connection.send(message) {
+                warn!("Not connected to the server");
+            }
+        }else{
+            warn!("Invalid connection");
+        }
+    }
+
+    /// Sent to the server's `Authenticator` on every connect.
+    pub fn set_credentials(&mut self, name: &str, credentials: Vec<u8>) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.credentials = credentials,
+            None => warn!("Invalid connection")
+        }
+    }
+
+    pub fn connection_state(&self, name: &str) -> ClientConnectionState {
+        match self.0.get(name) {
+            Some(connection) => connection.connection_state(),
+            None => ClientConnectionState::Disconnected
+        }
+    }
+
+    /// Messages sent during a frame are packed into batches of up to this many bytes at the end
+    /// of it, 0 sends every message right away. By default it is `DEFAULT_BATCH_SIZE`.
+    /// Memory connections never batch, their messages are not serialized.
+    pub fn set_batch_size(&mut self, name: &str, batch_size: usize) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.batch_size = batch_size,
+            None => warn!("Invalid connection")
+        }
+    }
+
+    pub fn set_reconnect(&mut self, name: &str, settings: ReconnectSettings) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.reconnect = settings,
+            None => warn!("Invalid connection")
+        }
+    }
+
+    /// Starts retrying again a connection that gave up.
+    pub fn reconnect(&mut self, name: &str) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.reconnect_state.connected(),
+            None => warn!("Invalid connection")
+        }
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl ServerConnections {
     pub fn send_for_all_clients(&mut self, message: &dyn MessageTrait, name: &String) {
         let connection = self.0.get_mut(name);
 
         if let Some(connection) = connection {
-            match connection {
-                ServerConnectionType::Tcp(tcp_connection) => {
-                    for (_,client_connection) in tcp_connection.connections.iter_mut() {
-                        client_connection.send_message(message, tcp_connection.runtime.as_ref().unwrap());
-                    }
-                }
+            for client in connection.sessions.clients() {
+                connection.send(&client, message);
             }
         }else {
             warn!("Invalid connection");
@@ -185,17 +656,9 @@ impl ServerConnections {
         let connection = self.0.get_mut(name);
 
         if let Some(connection) = connection {
-            match connection {
-                ServerConnectionType::Tcp(tcp_connection) => {
-                    for client in to_clients{
-                        let client_connection = tcp_connection.connections.get_mut(client);
-
-                        if let Some(client_connection) = client_connection{
-                            client_connection.send_message(message,tcp_connection.runtime.as_ref().unwrap());
-                        }else{
-                            warn!("Client not conneceted");
-                        }
-                    }
+            for client in to_clients{
+                if !connection.send(client, message) {
+                    warn!("Client not conneceted");
                 }
             }
         }else{
@@ -207,23 +670,123 @@ impl ServerConnections {
         let connection = self.0.get_mut(name);
 
         if let Some(connection) = connection {
-            match connection {
-                ServerConnectionType::Tcp(tcp_connection) => {
-                    let client_connection = tcp_connection.connections.get_mut(uuid);
-                    
-                    if let Some(client_connection) = client_connection{
-                        client_connection.send_message(message,tcp_connection.runtime.as_ref().unwrap());
-                    }else{
-                        warn!("Client not conneceted");
-                    }
-                }
+            if !connection.send(uuid, message) {
+                warn!("Client not conneceted");
             }
         }else{
             warn!("Invalid connection");
         }
     }
 
-    pub fn new_server_tcp_connection(&mut self, settings: ServerTcpSettings, name: &'static str) {
+    /// Tells the client why, then closes its link. The session ends right away, so the
+    /// client can not resume it, and `ClientDiconnected` is written on the next frame.
+    pub fn disconnect_client(&mut self, name: &str, uuid: &Uuid, reason: DisconnectReason) {
+        let Some(connection) = self.0.get_mut(name) else {
+            warn!("Invalid connection");
+            return;
+        };
+
+        let transport_id = connection.sessions.transport_of(uuid);
+
+        if !connection.sessions.close(uuid) {
+            warn!("Client not conneceted");
+            return;
+        }
+
+        if let Some(transport_id) = transport_id {
+            connection.close_link(transport_id, reason);
+        }
+
+        connection.closed_sessions.push(*uuid);
+    }
+
+    pub fn disconnect_all_clients(&mut self, name: &str, reason: DisconnectReason) {
+        let Some(connection) = self.0.get(name) else {
+            warn!("Invalid connection");
+            return;
+        };
+
+        for client in connection.clients() {
+            self.disconnect_client(name, &client, reason.clone());
+        }
+    }
+
+    /// Bans the uuid and the address of the client, then disconnects it with `DisconnectReason::Banned`.
+    pub fn ban_client(&mut self, name: &str, uuid: &Uuid) {
+        let Some(connection) = self.0.get_mut(name) else {
+            warn!("Invalid connection");
+            return;
+        };
+
+        let address = connection.sessions.transport_of(uuid)
+            .and_then(|transport_id| connection.transport.client_address(&transport_id));
+
+        connection.bans.clients.insert(*uuid);
+
+        if let Some(address) = address {
+            connection.bans.addresses.insert(address.ip());
+        }
+
+        self.disconnect_client(name, uuid, DisconnectReason::Banned);
+    }
+
+    pub fn set_authenticator(&mut self, name: &str, authenticator: Authenticator) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.authenticator = authenticator,
+            None => warn!("Invalid connection")
+        }
+    }
+
+    /// Answers an `AuthRequest`.
+    pub fn accept_client(&mut self, name: &str, request: &Uuid) {
+        self.answer_auth_request(name, request, true);
+    }
+
+    /// Answers an `AuthRequest`, the client is disconnected with `DisconnectReason::Unauthorized`.
+    pub fn reject_client(&mut self, name: &str, request: &Uuid) {
+        self.answer_auth_request(name, request, false);
+    }
+
+    fn answer_auth_request(&mut self, name: &str, request: &Uuid, accepted: bool) {
+        let Some(connection) = self.0.get_mut(name) else {
+            warn!("Invalid connection");
+            return;
+        };
+
+        match connection.pending_auth.get_mut(request) {
+            Some(pending_auth) => pending_auth.accepted = Some(accepted),
+            None => warn!("Invalid auth request")
+        }
+    }
+
+    pub fn ban_address(&mut self, name: &str, address: IpAddr) {
+        match self.0.get_mut(name) {
+            Some(connection) => {
+                connection.bans.addresses.insert(address);
+            }
+            None => warn!("Invalid connection")
+        }
+    }
+
+    pub fn unban_client(&mut self, name: &str, uuid: &Uuid) {
+        match self.0.get_mut(name) {
+            Some(connection) => {
+                connection.bans.clients.remove(uuid);
+            }
+            None => warn!("Invalid connection")
+        }
+    }
+
+    pub fn unban_address(&mut self, name: &str, address: &IpAddr) {
+        match self.0.get_mut(name) {
+            Some(connection) => {
+                connection.bans.addresses.remove(address);
+            }
+            None => warn!("Invalid connection")
+        }
+    }
+
+    pub fn new_server_connection<T: ServerTransport>(&mut self, transport: T, name: &'static str) {
         if self.0.contains_key(name) {
             warn!("You already have a connection with this name");
             return;
@@ -237,6 +800,110 @@ impl ServerConnections {
             }
         };
 
-        self.0.insert(parsed_name, ServerConnectionType::Tcp(ServerTcpConnection::new(settings, name)));
+        self.0.insert(parsed_name, ServerConnection {
+            name,
+            transport: Box::new(transport),
+            heartbeat: HeartbeatSettings::default(),
+            links: HashMap::new(),
+            sessions: ServerSessions::default(),
+            bans: BanList::default(),
+            closing: HashMap::new(),
+            closed_sessions: Vec::new(),
+            authenticator: Authenticator::default(),
+            pending_auth: HashMap::new(),
+            protocol_violations: HashMap::new(),
+            rejected_frames: HashMap::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            outgoing: HashMap::new(),
+            routes: Arc::clone(&self.1)
+        });
+    }
+
+    pub fn new_server_tcp_connection(&mut self, settings: ServerTcpSettings, name: &'static str) {
+        self.new_server_connection(ServerTcpConnection::new(settings, name), name);
+    }
+
+    pub fn new_server_udp_connection(&mut self, settings: ServerUdpSettings, name: &'static str) {
+        self.new_server_connection(ServerUdpConnection::new(settings, name), name);
+    }
+
+    pub fn new_server_websocket_connection(&mut self, settings: ServerWebSocketSettings, name: &'static str) {
+        self.new_server_connection(ServerWebSocketConnection::new(settings, name), name);
+    }
+
+    pub fn new_server_quic_connection(&mut self, settings: ServerQuicSettings, name: &'static str) {
+        self.new_server_connection(ServerQuicConnection::new(settings, name), name);
+    }
+
+    pub fn new_server_memory_connection(&mut self, settings: ServerMemorySettings, name: &'static str) {
+        self.new_server_connection(ServerMemoryConnection::new(settings, name), name);
+    }
+
+    #[cfg(unix)]
+    pub fn new_server_unix_connection(&mut self, settings: ServerUnixSettings, name: &'static str) {
+        self.new_server_connection(ServerUnixConnection::new(settings, name), name);
+    }
+
+    /// Runs the connection through a link conditioner, to test it under latency and loss.
+    pub fn set_link_conditioner(&mut self, name: &str, settings: LinkConditionerSettings) {
+        let Some(mut connection) = self.0.remove(name) else {
+            warn!("Invalid connection");
+            return;
+        };
+
+        connection.transport = Box::new(ConditionedServerTransport::new(connection.transport, settings).with_routes(Arc::clone(&self.1)));
+
+        self.0.insert(name.to_string(), connection);
+    }
+
+    pub fn set_heartbeat(&mut self, name: &str, settings: HeartbeatSettings) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.heartbeat = settings,
+            None => warn!("Invalid connection")
+        }
+    }
+
+    /// How long a client that lost its link keeps its uuid to resume the session.
+    /// `ClientDiconnected` is only written once this has passed, by default it is zero.
+    pub fn set_session_grace_period(&mut self, name: &str, grace_period: Duration) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.sessions.grace_period = grace_period,
+            None => warn!("Invalid connection")
+        }
+    }
+
+    /// Messages sent to a client during a frame are packed into batches of up to this many bytes
+    /// at the end of it, 0 sends every message right away. By default it is `DEFAULT_BATCH_SIZE`.
+    /// Memory connections never batch, their messages are not serialized.
+    pub fn set_batch_size(&mut self, name: &str, batch_size: usize) {
+        match self.0.get_mut(name) {
+            Some(connection) => connection.batch_size = batch_size,
+            None => warn!("Invalid connection")
+        }
+    }
+
+    pub fn client_rtt(&self, name: &str, client: &Uuid) -> Option<RttStats> {
+        self.0.get(name).and_then(|connection| connection.rtt(client))
+    }
+
+    pub fn memory_connector(&self, name: &str) -> Option<MemoryConnector> {
+        let Some(connection) = self.0.get(name) else {
+            warn!("Invalid connection");
+            return None;
+        };
+
+        let mut transport: &dyn Any = connection.transport.as_ref();
+
+        if let Some(conditioned) = transport.downcast_ref::<ConditionedServerTransport>() {
+            transport = conditioned.inner.as_ref();
+        }
+
+        match transport.downcast_ref::<ServerMemoryConnection>() {
+            Some(memory_connection) => Some(memory_connection.connector()),
+            None => {
+                warn!("Connection is not a memory connection");
+                None
+            }
+        }
     }
 }
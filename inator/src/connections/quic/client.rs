@@ -0,0 +1,333 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use quinn::{Connection as QuinnConnection, Endpoint, VarInt};
+use quinn::rustls::pki_types::CertificateDer;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use crate::connections::{Connection, ConnectionsType, MessageChannel};
+use crate::connections::quic::connection::QuicConnection;
+use crate::connections::quic::tls::{client_config, ClientQuicTls};
+use crate::connections::transport::{ClientTransport, TransportState};
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+pub struct ClientQuicSettings {
+    pub(crate) address: IpAddr,
+    pub(crate) port: u16,
+    pub(crate) server_name: String,
+    pub(crate) tls: Option<ClientQuicTls>
+}
+
+pub struct ClientQuicConnection {
+    pub(crate) settings: ClientQuicSettings,
+    pub(crate) name: &'static str,
+    pub(crate) started: bool,
+    pub(crate) runtime: Option<Handle>,
+    pub(crate) dropped: Arc<AtomicBool>,
+    pub(crate) endpoint: Option<Endpoint>,
+    pub(crate) local_quic_connection: Option<QuicConnection>,
+    pub(crate) cancel_token: Arc<CancellationToken>,
+    pub(crate) connection_up_sender: Arc<UnboundedSender<(Endpoint, QuinnConnection)>>,
+    pub(crate) connection_up_receiver: UnboundedReceiver<(Endpoint, QuinnConnection)>
+}
+
+impl Default for ClientQuicSettings {
+    fn default() -> Self {
+        ClientQuicSettings {
+            address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            port: 8083,
+            server_name: "localhost".to_string(),
+            tls: None
+        }
+    }
+}
+
+impl ClientQuicSettings {
+    pub fn new(address: IpAddr, port: u16) -> Self {
+        Self {
+            address,
+            port,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_server_name(mut self, server_name: &str) -> Self {
+        self.server_name = server_name.to_string();
+        self
+    }
+
+    /// One of the `with_*` certificate options is required, the client does not connect without it.
+    pub fn with_trusted_certificate(mut self, certificate: CertificateDer<'static>) -> Self {
+        self.tls = Some(ClientQuicTls::TrustedCertificate(certificate));
+        self
+    }
+
+    /// Pins the SHA-256 fingerprint of the server certificate, see `certificate_fingerprint`.
+    /// The server prints it on start, which also works for its self signed certificate.
+    pub fn with_pinned_fingerprint(mut self, fingerprint: [u8; 32]) -> Self {
+        self.tls = Some(ClientQuicTls::PinnedFingerprint(fingerprint));
+        self
+    }
+
+    pub fn with_unverified_tls(mut self) -> Self {
+        self.tls = Some(ClientQuicTls::AcceptAnyCertificate);
+        self
+    }
+}
+
+impl ClientQuicConnection {
+    pub fn new(settings: ClientQuicSettings, name: &'static str) -> ClientQuicConnection {
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<(Endpoint, QuinnConnection)>();
+
+        ClientQuicConnection {
+            settings,
+            name,
+            started: false,
+            runtime: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+            endpoint: None,
+            local_quic_connection: None,
+            cancel_token: Arc::new(CancellationToken::new()),
+            connection_up_sender: Arc::new(connection_up_sender),
+            connection_up_receiver
+        }
+    }
+}
+
+impl Connection for ClientQuicConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        let settings = &self.settings;
+
+        let Some(tls) = settings.tls.as_ref() else {
+            eprintln!("Quic client {} needs a trusted certificate, a pinned fingerprint or with_unverified_tls", self.name);
+            return;
+        };
+
+        let Some(client_config) = client_config(tls) else {
+            return;
+        };
+
+        let server_addr = SocketAddr::new(settings.address, settings.port);
+        let local_addr = match settings.address {
+            IpAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            IpAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        };
+        let server_name = settings.server_name.clone();
+        let dropped = Arc::clone(&self.dropped);
+        let cancel_token = Arc::clone(&self.cancel_token);
+        let connection_up_sender = Arc::clone(&self.connection_up_sender);
+
+        self.started = true;
+        self.dropped.store(false, Ordering::SeqCst);
+
+        self.runtime.get_or_insert_with(network_runtime).spawn(async move {
+            let mut endpoint = match Endpoint::client(local_addr) {
+                Ok(endpoint) => endpoint,
+                Err(e) => {
+                    println!("Failed to bind quic endpoint: {}", e);
+                    dropped.store(true, Ordering::SeqCst);
+                    return;
+                }
+            };
+
+            endpoint.set_default_client_config(client_config);
+
+            let connecting = match endpoint.connect(server_addr, &server_name) {
+                Ok(connecting) => connecting,
+                Err(e) => {
+                    println!("Failed to connect to server: {}", e);
+                    dropped.store(true, Ordering::SeqCst);
+                    return;
+                }
+            };
+
+            let connection = match connecting.await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    println!("Failed to connect to server: {}", e);
+                    dropped.store(true, Ordering::SeqCst);
+                    return;
+                }
+            };
+
+            if cancel_token.is_cancelled() {
+                return;
+            }
+
+            connection_up_sender.send((endpoint, connection)).unwrap();
+        });
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(local_quic_connection) = self.local_quic_connection.take() {
+            drop(local_quic_connection);
+        }
+
+        if let Some(endpoint) = self.endpoint.take() {
+            endpoint.close(VarInt::from_u32(0), b"cancelled");
+        }
+
+        self.cancel_token.cancel();
+        self.cancel_token = Arc::new(CancellationToken::new());
+        self.dropped.store(true, Ordering::SeqCst);
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(mut local_quic_connection) = self.local_quic_connection.take() {
+            local_quic_connection.send_disconnect();
+        }
+
+        if let Some(endpoint) = self.endpoint.take() {
+            endpoint.close(VarInt::from_u32(0), b"disconnect");
+        }
+
+        self.runtime.take();
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ClientTransport for ClientQuicConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Quic
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.local_quic_connection.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok((endpoint, quinn_connection)) = self.connection_up_receiver.try_recv() {
+            if let Some(local_quic_connection) = self.local_quic_connection.take() {
+                drop(local_quic_connection);
+            }
+
+            let mut quic_connection = QuicConnection::new(quinn_connection, self.name, NetworkSide::Client, Arc::clone(&self.cancel_token));
+
+            quic_connection.start_listening(self.runtime.as_ref().unwrap());
+
+            self.endpoint = Some(endpoint);
+            self.local_quic_connection = Some(quic_connection);
+        } else if self.started && self.local_quic_connection.is_none() && self.dropped.load(Ordering::SeqCst) {
+            self.started = false;
+        }
+
+        let connection_down = match self.local_quic_connection.as_mut() {
+            Some(local_quic_connection) => local_quic_connection.connection_down_receiver.try_recv().is_ok(),
+            None => false
+        };
+
+        if connection_down {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>> {
+        let mut received = Vec::new();
+
+        if let Some(local_quic_connection) = self.local_quic_connection.as_mut() {
+            while let Ok(message) = local_quic_connection.message_received_receiver.try_recv() {
+                received.push(message);
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, message: &dyn MessageTrait, channel: MessageChannel) -> bool {
+        match self.local_quic_connection.as_mut() {
+            Some(local_quic_connection) => {
+                local_quic_connection.send_message(message, channel, self.runtime.as_ref().unwrap());
+                true
+            }
+            None => false
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.cancel_connection()
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::*;
+    use crate::connections::heartbeat::HeartbeatPing;
+    use crate::connections::quic::server::{ServerQuicConnection, ServerQuicSettings};
+    use crate::connections::transport::ServerTransport;
+
+    #[test]
+    fn message_reaches_the_server_over_loopback() {
+        let port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let address = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut server = ServerQuicConnection::new(ServerQuicSettings::new(address, port, 0), "quic");
+        let mut client = ClientQuicConnection::new(ClientQuicSettings::new(address, port).with_unverified_tls(), "quic");
+        let mut accepted = Vec::new();
+        let mut received = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        server.start();
+        client.start();
+
+        while client.state() != TransportState::Connected {
+            assert!(Instant::now() < deadline, "timed out connecting");
+
+            server.update();
+            client.update();
+            accepted.extend(server.poll_accepted());
+
+            if client.state() == TransportState::Stopped {
+                client.start();
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(client.send(&HeartbeatPing { sent_at: 42 }, MessageChannel::ReliableOrdered));
+
+        while received.is_empty() {
+            assert!(Instant::now() < deadline, "timed out receiving");
+
+            server.update();
+            accepted.extend(server.poll_accepted());
+            received.extend(server.poll_received());
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let (sender, message) = &received[0];
+
+        assert_eq!(accepted, vec![*sender]);
+        assert_eq!(message.as_any().downcast_ref::<HeartbeatPing>().unwrap().sent_at, 42);
+    }
+}
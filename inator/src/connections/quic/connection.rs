@@ -0,0 +1,284 @@
+use std::sync::Arc;
+use bytes::Bytes;
+use quinn::{Connection as QuinnConnection, RecvStream, SendStream, VarInt};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use crate::connections::MessageChannel;
+use crate::connections::udp::channel::sequence_greater_than;
+use crate::NetworkSide;
+use crate::systems::messaging::{deserialize_message, encode_message, MessageTrait};
+
+pub(crate) const MAX_QUIC_FRAME_SIZE: usize = 16 * 1024 * 1024;
+const DATAGRAM_HEADER_SIZE: usize = 3;
+
+pub struct QuicConnection {
+    pub connection: QuinnConnection,
+    pub connection_name: &'static str,
+    pub network_side: NetworkSide,
+    pub uuid: Option<Uuid>,
+    pub cancellation_token: Arc<CancellationToken>,
+    pub connection_down_sender: Arc<UnboundedSender<()>>,
+    pub connection_down_receiver: UnboundedReceiver<()>,
+    pub message_received_sender: Arc<UnboundedSender<Box<dyn MessageTrait>>>,
+    pub message_received_receiver: UnboundedReceiver<Box<dyn MessageTrait>>,
+    pub listening: bool,
+    pub(crate) ordered_sender: UnboundedSender<Vec<u8>>,
+    pub(crate) ordered_receiver: Option<UnboundedReceiver<Vec<u8>>>,
+    pub(crate) datagram_sequence: u16
+}
+
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(4 + payload.len());
+
+    framed.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    framed.extend_from_slice(payload);
+
+    framed
+}
+
+async fn read_frame(stream: &mut RecvStream) -> Option<Vec<u8>> {
+    let mut len_buf = [0u8; 4];
+
+    stream.read_exact(&mut len_buf).await.ok()?;
+
+    let len = u32::from_le_bytes(len_buf) as usize;
+
+    if len > MAX_QUIC_FRAME_SIZE {
+        eprintln!("Quic frame of {} bytes is too big", len);
+        return None;
+    }
+
+    let mut payload = vec![0u8; len];
+
+    stream.read_exact(&mut payload).await.ok()?;
+
+    Some(payload)
+}
+
+fn deliver(payload: &[u8], message_received_sender: &UnboundedSender<Box<dyn MessageTrait>>) {
+    if let Some(message) = deserialize_message(payload) {
+        let _ = message_received_sender.send(message);
+    } else {
+        eprintln!("Message not registered or failed to deserialize");
+    }
+}
+
+async fn read_stream(mut stream: RecvStream, message_received_sender: Arc<UnboundedSender<Box<dyn MessageTrait>>>) {
+    while let Some(payload) = read_frame(&mut stream).await {
+        deliver(&payload, &message_received_sender);
+    }
+}
+
+impl Drop for QuicConnection {
+    fn drop(&mut self) {
+        self.connection.close(VarInt::from_u32(0), b"dropped");
+    }
+}
+
+impl QuicConnection {
+    pub fn new(connection: QuinnConnection, connection_name: &'static str, network_side: NetworkSide, cancellation_token: Arc<CancellationToken>) -> Self {
+        let (connection_down_sender, connection_down_receiver) = unbounded_channel::<()>();
+        let (message_received_sender, message_received_receiver) = unbounded_channel::<Box<dyn MessageTrait>>();
+        let (ordered_sender, ordered_receiver) = unbounded_channel::<Vec<u8>>();
+
+        QuicConnection {
+            connection,
+            connection_name,
+            network_side,
+            uuid: if network_side == NetworkSide::Server {Some(Uuid::new_v4())} else {None},
+            cancellation_token: Arc::clone(&cancellation_token),
+            connection_down_sender: Arc::new(connection_down_sender),
+            connection_down_receiver,
+            message_received_sender: Arc::new(message_received_sender),
+            message_received_receiver,
+            listening: false,
+            ordered_sender,
+            ordered_receiver: Some(ordered_receiver),
+            datagram_sequence: 0
+        }
+    }
+
+    pub fn start_listening(&mut self, runtime: &Handle) {
+        let Some(mut ordered_receiver) = self.ordered_receiver.take() else {
+            return;
+        };
+
+        let connection = self.connection.clone();
+        let connection_down_sender = Arc::clone(&self.connection_down_sender);
+        let message_received_sender = Arc::clone(&self.message_received_sender);
+        let cancellation_token = Arc::clone(&self.cancellation_token);
+        let network_side = self.network_side;
+
+        self.listening = true;
+
+        let writer_connection = connection.clone();
+        let writer_cancellation_token = Arc::clone(&cancellation_token);
+
+        runtime.spawn(async move {
+            let mut ordered_stream: Option<SendStream> = None;
+
+            loop {
+                tokio::select! {
+                    _ = writer_cancellation_token.cancelled() => break,
+
+                    framed = ordered_receiver.recv() => {
+                        let Some(framed) = framed else {
+                            break;
+                        };
+
+                        if ordered_stream.is_none() {
+                            match writer_connection.open_uni().await {
+                                Ok(stream) => ordered_stream = Some(stream),
+                                Err(e) => {
+                                    eprintln!("Failed to open ordered stream: {:?}", e);
+                                    break;
+                                }
+                            }
+                        }
+
+                        if let Some(stream) = ordered_stream.as_mut()
+                            && let Err(e) = stream.write_all(&framed).await {
+                            eprintln!("Failed to send message: {:?}", e);
+                            eprintln!("From {:?}", network_side);
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        runtime.spawn(async move {
+            let mut last_sequenced: Option<u16> = None;
+
+            loop {
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+
+                    stream = connection.accept_uni() => {
+                        match stream {
+                            Ok(stream) => {
+                                tokio::spawn(read_stream(stream, Arc::clone(&message_received_sender)));
+                            }
+                            Err(e) => {
+                                println!("Quic connection closed: {:?}", e);
+                                println!("From {:?}", network_side);
+
+                                let _ = connection_down_sender.send(());
+
+                                break;
+                            }
+                        }
+                    }
+
+                    datagram = connection.read_datagram() => {
+                        match datagram {
+                            Ok(datagram) => {
+                                if datagram.len() < DATAGRAM_HEADER_SIZE {
+                                    eprintln!("Invalid quic datagram received");
+                                    continue;
+                                }
+
+                                let channel = MessageChannel::from_byte(datagram[0]);
+                                let sequence = u16::from_le_bytes([datagram[1], datagram[2]]);
+
+                                if channel == Some(MessageChannel::UnreliableSequenced) {
+                                    if let Some(last) = last_sequenced
+                                        && !sequence_greater_than(sequence, last) {
+                                        continue;
+                                    }
+
+                                    last_sequenced = Some(sequence);
+                                }
+
+                                deliver(&datagram[DATAGRAM_HEADER_SIZE..], &message_received_sender);
+                            }
+                            Err(e) => {
+                                println!("Quic connection closed: {:?}", e);
+                                println!("From {:?}", network_side);
+
+                                let _ = connection_down_sender.send(());
+
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn send_message(&mut self, message: &dyn MessageTrait, channel: MessageChannel, runtime: &Handle) {
+        let payload = match encode_message(message) {
+            Some(payload) => payload,
+            None => return,
+        };
+
+        if payload.len() > MAX_QUIC_FRAME_SIZE {
+            eprintln!("Message of {} bytes is too big for a quic frame", payload.len());
+            return;
+        }
+
+        match channel {
+            MessageChannel::ReliableOrdered => {
+                if self.ordered_sender.send(frame(&payload)).is_err() {
+                    eprintln!("Quic ordered stream is closed");
+                }
+            }
+            MessageChannel::ReliableUnordered => {
+                self.send_on_new_stream(payload, runtime);
+            }
+            MessageChannel::Unreliable | MessageChannel::UnreliableSequenced => {
+                let fits = self.connection.max_datagram_size()
+                    .is_some_and(|max_size| payload.len() + DATAGRAM_HEADER_SIZE <= max_size);
+
+                if !fits {
+                    self.send_on_new_stream(payload, runtime);
+                    return;
+                }
+
+                let sequence = self.datagram_sequence;
+
+                self.datagram_sequence = sequence.wrapping_add(1);
+
+                let mut datagram = Vec::with_capacity(DATAGRAM_HEADER_SIZE + payload.len());
+
+                datagram.push(channel.to_byte());
+                datagram.extend_from_slice(&sequence.to_le_bytes());
+                datagram.extend_from_slice(&payload);
+
+                if let Err(e) = self.connection.send_datagram(Bytes::from(datagram)) {
+                    eprintln!("Failed to send datagram: {:?}", e);
+                }
+            }
+        }
+    }
+
+    pub fn send_disconnect(&mut self) {
+        self.connection.close(VarInt::from_u32(0), b"disconnect");
+    }
+
+    fn send_on_new_stream(&self, payload: Vec<u8>, runtime: &Handle) {
+        let connection = self.connection.clone();
+        let network_side = self.network_side;
+
+        runtime.spawn(async move {
+            let mut stream = match connection.open_uni().await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Failed to open stream: {:?}", e);
+                    return;
+                }
+            };
+
+            if let Err(e) = stream.write_all(&frame(&payload)).await {
+                eprintln!("Failed to send message: {:?}", e);
+                eprintln!("From {:?}", network_side);
+                return;
+            }
+
+            let _ = stream.finish();
+        });
+    }
+}
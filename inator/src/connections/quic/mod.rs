@@ -0,0 +1,4 @@
+﻿pub mod server;
+pub mod client;
+pub mod connection;
+mod tls;
\ No newline at end of file
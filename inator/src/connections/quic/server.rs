@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use quinn::{Connection as QuinnConnection, Endpoint, VarInt};
+use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use crate::connections::{BIND_RETRY_INTERVAL, Connection, ConnectionsType, MessageChannel};
+use crate::connections::quic::connection::QuicConnection;
+use crate::connections::quic::tls::server_config;
+use crate::connections::tls::self_signed_certificate;
+use crate::connections::transport::{ServerTransport, TransportState};
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+pub struct ServerQuicSettings {
+    pub(crate) address: IpAddr,
+    pub(crate) port: u16,
+    pub(crate) max_connections: usize,
+    pub(crate) server_name: String,
+    pub(crate) certificate: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>
+}
+
+pub struct ServerQuicConnection {
+    pub(crate) settings: ServerQuicSettings,
+    pub(crate) name: &'static str,
+    pub(crate) endpoint: Option<Endpoint>,
+    pub(crate) started: bool,
+    pub(crate) runtime: Option<Handle>,
+    pub(crate) dropped: Arc<AtomicBool>,
+    pub(crate) cancel_token: Arc<CancellationToken>,
+    pub(crate) connection_down_sender: Arc<UnboundedSender<()>>,
+    pub(crate) connection_down_receiver: UnboundedReceiver<()>,
+    pub(crate) connection_up_sender: Arc<UnboundedSender<Endpoint>>,
+    pub(crate) connection_up_receiver: UnboundedReceiver<Endpoint>,
+    pub(crate) client_connected_sender: Arc<UnboundedSender<QuinnConnection>>,
+    pub(crate) client_connected_receiver: UnboundedReceiver<QuinnConnection>,
+    pub(crate) connections: HashMap<Uuid, QuicConnection>
+}
+
+impl Default for ServerQuicSettings {
+    fn default() -> Self {
+        ServerQuicSettings {
+            address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            port: 8083,
+            max_connections: 0,
+            server_name: "localhost".to_string(),
+            certificate: None
+        }
+    }
+}
+
+impl ServerQuicSettings {
+    pub fn new(address: IpAddr, port: u16, max_connections: usize) -> Self {
+        Self {
+            address,
+            port,
+            max_connections,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_server_name(mut self, server_name: &str) -> Self {
+        self.server_name = server_name.to_string();
+        self
+    }
+
+    pub fn with_certificate(mut self, cert_chain: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>) -> Self {
+        self.certificate = Some((cert_chain, key));
+        self
+    }
+}
+
+impl ServerQuicConnection {
+    pub fn new(settings: ServerQuicSettings, name: &'static str) -> ServerQuicConnection {
+        let (connection_down_sender, connection_down_receiver) = unbounded_channel::<()>();
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<Endpoint>();
+        let (client_connected_sender, client_connected_receiver) = unbounded_channel::<QuinnConnection>();
+
+        ServerQuicConnection {
+            settings,
+            name,
+            endpoint: None,
+            started: false,
+            runtime: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+            cancel_token: Arc::new(CancellationToken::new()),
+            connection_down_sender: Arc::new(connection_down_sender),
+            connection_down_receiver,
+            connection_up_sender: Arc::new(connection_up_sender),
+            connection_up_receiver,
+            client_connected_sender: Arc::new(client_connected_sender),
+            client_connected_receiver,
+            connections: HashMap::new()
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.settings.max_connections > 0 && self.connections.len() >= self.settings.max_connections
+    }
+}
+
+impl Connection for ServerQuicConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        let settings = &self.settings;
+        let certificate = match &settings.certificate {
+            Some((cert_chain, key)) => Some((cert_chain.clone(), key.clone_key())),
+            None => self_signed_certificate(&settings.server_name),
+        };
+
+        let Some(server_config) = certificate.and_then(|(cert_chain, key)| server_config(cert_chain, key)) else {
+            return;
+        };
+
+        let address = SocketAddr::new(settings.address, settings.port);
+        let dropped = Arc::clone(&self.dropped);
+        let connection_up_sender = Arc::clone(&self.connection_up_sender);
+        let connection_down_sender = Arc::clone(&self.connection_down_sender);
+        let client_connected_sender = Arc::clone(&self.client_connected_sender);
+        let cancel_token = Arc::clone(&self.cancel_token);
+
+        self.started = true;
+
+        self.runtime.get_or_insert_with(network_runtime).spawn(async move {
+            dropped.store(false, Ordering::SeqCst);
+
+            let endpoint = loop {
+                match Endpoint::server(server_config.clone(), address) {
+                    Ok(endpoint) => break endpoint,
+                    Err(e) => {
+                        println!("Error on bind: {}, trying again...", e);
+
+                        if dropped.load(Ordering::SeqCst) {
+                            return;
+                        }
+
+                        tokio::time::sleep(BIND_RETRY_INTERVAL).await;
+                    }
+                };
+            };
+
+            if dropped.load(Ordering::SeqCst) {
+                endpoint.close(VarInt::from_u32(0), b"dropped");
+                return;
+            }
+
+            println!("Server quic binded successfully!");
+
+            connection_up_sender.send(endpoint.clone()).unwrap();
+
+            loop {
+                tokio::select! {
+                    _ = cancel_token.cancelled() => {
+                        break;
+                    },
+                    incoming = endpoint.accept() => {
+                        let Some(incoming) = incoming else {
+                            println!("Endpoint was probably closed manually");
+
+                            connection_down_sender.send(()).unwrap();
+
+                            break;
+                        };
+
+                        let client_connected_sender = Arc::clone(&client_connected_sender);
+
+                        tokio::spawn(async move {
+                            let addr = incoming.remote_address();
+
+                            match incoming.await {
+                                Ok(connection) => {
+                                    println!("Accepted connection from {}", addr);
+
+                                    let _ = client_connected_sender.send(connection);
+                                }
+                                Err(e) => {
+                                    eprintln!("Quic handshake failed with {}: {:?}", addr, e);
+                                }
+                            }
+                        });
+                    }
+                }
+
+                if dropped.load(Ordering::SeqCst) {
+                    endpoint.close(VarInt::from_u32(0), b"dropped");
+                    break;
+                }
+            }
+        });
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(endpoint) = self.endpoint.take() {
+            endpoint.close(VarInt::from_u32(0), b"cancelled");
+        }
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        for client_connection in self.connections.values_mut() {
+            client_connection.send_disconnect();
+        }
+
+        if let Some(endpoint) = self.endpoint.take() {
+            endpoint.close(VarInt::from_u32(0), b"disconnect");
+        }
+
+        self.runtime.take();
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ServerTransport for ServerQuicConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Quic
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.endpoint.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok(endpoint) = self.connection_up_receiver.try_recv() {
+            self.endpoint = Some(endpoint);
+        }
+
+        if self.connection_down_receiver.try_recv().is_ok() {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_accepted(&mut self) -> Vec<Uuid> {
+        let mut accepted = Vec::new();
+
+        while let Ok(quinn_connection) = self.client_connected_receiver.try_recv() {
+            if self.is_full() {
+                println!("Connection is full, rejecting connection to {}", quinn_connection.remote_address());
+
+                quinn_connection.close(VarInt::from_u32(0), b"full");
+                continue
+            }
+
+            let mut quic_connection = QuicConnection::new(quinn_connection, self.name, NetworkSide::Server, Arc::clone(&self.cancel_token));
+            let current_uuid = quic_connection.uuid.unwrap();
+
+            quic_connection.start_listening(self.runtime.as_ref().unwrap());
+
+            self.connections.insert(current_uuid,quic_connection);
+            accepted.push(current_uuid);
+        }
+
+        accepted
+    }
+
+    fn poll_disconnected(&mut self) -> Vec<Uuid> {
+        let mut disconnected = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            if client_connection.connection_down_receiver.try_recv().is_ok() {
+                client_connection.listening = false;
+
+                disconnected.push(*uuid);
+            }
+        }
+
+        for uuid in disconnected.iter() {
+            self.connections.remove(uuid);
+        }
+
+        disconnected
+    }
+
+    fn poll_received(&mut self) -> Vec<(Uuid, Box<dyn MessageTrait>)> {
+        let mut received = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            while let Ok(message) = client_connection.message_received_receiver.try_recv() {
+                received.push((*uuid, message));
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, client: &Uuid, message: &dyn MessageTrait, channel: MessageChannel) -> bool {
+        match self.connections.get_mut(client) {
+            Some(client_connection) => {
+                client_connection.send_message(message, channel, self.runtime.as_ref().unwrap());
+                true
+            }
+            None => false
+        }
+    }
+
+    fn disconnect_client(&mut self, client: &Uuid) {
+        if let Some(mut client_connection) = self.connections.remove(client) {
+            client_connection.send_disconnect();
+        }
+    }
+
+    fn clients(&self) -> Vec<Uuid> {
+        self.connections.keys().copied().collect()
+    }
+
+    fn client_address(&self, client: &Uuid) -> Option<SocketAddr> {
+        self.connections.get(client).map(|client_connection| client_connection.connection.remote_address())
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use quinn::rustls::{ClientConfig as RustlsClientConfig, RootCertStore};
+use quinn::{ClientConfig, ServerConfig};
+use crate::connections::tls::{certificate_fingerprint, fingerprint_to_hex, FingerprintVerifier};
+
+/// How the client checks the server certificate, the same choices as `ClientTcpTls`.
+pub enum ClientQuicTls {
+    TrustedCertificate(CertificateDer<'static>),
+    PinnedFingerprint([u8; 32]),
+    /// Encrypts the traffic without checking who is on the other side.
+    AcceptAnyCertificate
+}
+
+pub(crate) fn server_config(cert_chain: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>) -> Option<ServerConfig> {
+    if let Some(end_entity) = cert_chain.first() {
+        println!("Server quic certificate fingerprint: {}", fingerprint_to_hex(&certificate_fingerprint(end_entity)));
+    }
+
+    match ServerConfig::with_single_cert(cert_chain, key) {
+        Ok(server_config) => Some(server_config),
+        Err(e) => {
+            eprintln!("Invalid quic certificate: {:?}", e);
+            None
+        }
+    }
+}
+
+pub(crate) fn client_config(tls: &ClientQuicTls) -> Option<ClientConfig> {
+    let rustls_config = match tls {
+        ClientQuicTls::TrustedCertificate(certificate) => {
+            let mut roots = RootCertStore::empty();
+
+            if let Err(e) = roots.add(certificate.clone()) {
+                eprintln!("Invalid trusted certificate: {:?}", e);
+                return None;
+            }
+
+            RustlsClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth()
+        }
+        ClientQuicTls::PinnedFingerprint(fingerprint) => {
+            RustlsClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(FingerprintVerifier::new(Some(*fingerprint))))
+                .with_no_client_auth()
+        }
+        ClientQuicTls::AcceptAnyCertificate => {
+            RustlsClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(FingerprintVerifier::new(None)))
+                .with_no_client_auth()
+        }
+    };
+
+    match QuicClientConfig::try_from(rustls_config) {
+        Ok(quic_config) => Some(ClientConfig::new(Arc::new(quic_config))),
+        Err(e) => {
+            eprintln!("Invalid quic client config: {:?}", e);
+            None
+        }
+    }
+}
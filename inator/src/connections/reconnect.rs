@@ -0,0 +1,122 @@
+use std::time::Duration;
+use bevy::platform::time::Instant;
+use uuid::Uuid;
+
+/// How a client retries its connection. The first attempt after a drop is immediate, then the
+/// n-th failed attempt in a row waits `initial_delay * multiplier^(n-1)`, capped at `max_delay`.
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectSettings {
+    pub(crate) initial_delay: Duration,
+    pub(crate) max_delay: Duration,
+    pub(crate) multiplier: f32,
+    pub(crate) jitter: f32,
+    pub(crate) max_attempts: Option<u32>
+}
+
+/// Retry state of one client connection.
+#[derive(Default)]
+pub(crate) struct ReconnectState {
+    attempts: u32,
+    attempting: bool,
+    next_attempt: Option<Instant>,
+    gave_up: bool
+}
+
+impl Default for ReconnectSettings {
+    fn default() -> Self {
+        ReconnectSettings {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.25,
+            max_attempts: None
+        }
+    }
+}
+
+impl ReconnectSettings {
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            initial_delay,
+            max_delay,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f32) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Up to this fraction of each delay is taken off at random, so clients dropped together
+    /// do not all retry at the same time.
+    pub fn with_jitter(mut self, jitter: f32) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Gives up after this many failed attempts in a row, by default it never gives up.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts.max(1));
+        self
+    }
+
+    pub(crate) fn delay(&self, failed_attempts: u32) -> Duration {
+        let exponent = failed_attempts.saturating_sub(1).min(64) as i32;
+        let delay = (self.initial_delay.as_secs_f32() * self.multiplier.powi(exponent))
+            .min(self.max_delay.as_secs_f32());
+
+        // The low bits of a v4 uuid are random, enough to spread retries.
+        let random = Uuid::new_v4().as_u128() as u32 as f32 / u32::MAX as f32;
+
+        Duration::from_secs_f32(delay * (1.0 - self.jitter * random))
+    }
+}
+
+impl ReconnectState {
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub(crate) fn attempting(&self) -> bool {
+        self.attempting
+    }
+
+    pub(crate) fn gave_up(&self) -> bool {
+        self.gave_up
+    }
+
+    pub(crate) fn ready(&self) -> bool {
+        !self.gave_up && !self.attempting && self.next_attempt.is_none_or(|next_attempt| Instant::now() >= next_attempt)
+    }
+
+    /// Returns the number of this attempt, starting at 1.
+    pub(crate) fn attempt_started(&mut self) -> u32 {
+        self.attempts += 1;
+        self.attempting = true;
+        self.attempts
+    }
+
+    /// Schedules the next attempt, returns true when there are no attempts left.
+    pub(crate) fn attempt_failed(&mut self, settings: &ReconnectSettings) -> bool {
+        self.attempting = false;
+
+        if settings.max_attempts.is_some_and(|max_attempts| self.attempts >= max_attempts) {
+            self.gave_up = true;
+            return true;
+        }
+
+        self.next_attempt = Some(Instant::now() + settings.delay(self.attempts));
+        false
+    }
+
+    /// Stops retrying, used when the server closed the connection for a terminal reason.
+    pub(crate) fn stop(&mut self) {
+        self.attempting = false;
+        self.gave_up = true;
+    }
+
+    pub(crate) fn connected(&mut self) {
+        *self = ReconnectState::default();
+    }
+}
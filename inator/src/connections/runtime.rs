@@ -0,0 +1,71 @@
+use std::sync::{Arc, OnceLock};
+use bevy::app::App;
+use bevy::prelude::Resource;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+static NETWORK_RUNTIME: OnceLock<NetworkRuntime> = OnceLock::new();
+
+/// The tokio runtime connections spawn their tasks on. Unless one is installed, a runtime
+/// with one worker per core is created the first time a connection needs it and shared by all of them.
+///
+/// To configure it for an App, insert it as a resource before adding `ServerPlugin` or `ClientPlugin`,
+/// the connections of that App then run on it. Call `install` to change the default used by
+/// connections started by hand.
+#[derive(Resource, Clone)]
+pub struct NetworkRuntime {
+    handle: Handle,
+    /// `None` when the runtime belongs to the host application.
+    _runtime: Option<Arc<Runtime>>
+}
+
+impl NetworkRuntime {
+    /// A multi-threaded runtime with `worker_threads` workers, 0 for one per core.
+    pub fn new(worker_threads: usize) -> Self {
+        let mut builder = Builder::new_multi_thread();
+
+        if worker_threads > 0 {
+            builder.worker_threads(worker_threads);
+        }
+
+        let runtime = builder.thread_name("inator-network").enable_all().build().unwrap();
+
+        NetworkRuntime {
+            handle: runtime.handle().clone(),
+            _runtime: Some(Arc::new(runtime))
+        }
+    }
+
+    /// Runs connections on a runtime the host application already has. It must keep running
+    /// for as long as the connections do.
+    pub fn from_handle(handle: Handle) -> Self {
+        NetworkRuntime {
+            handle,
+            _runtime: None
+        }
+    }
+
+    /// The runtime connections use, creating the default one if none was installed.
+    pub fn current() -> Self {
+        NETWORK_RUNTIME.get_or_init(|| NetworkRuntime::new(0)).clone()
+    }
+
+    /// Makes this the default runtime. Returns false when one was already in use, which is kept.
+    pub fn install(self) -> bool {
+        NETWORK_RUNTIME.set(self).is_ok()
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+}
+
+pub(crate) fn network_runtime() -> Handle {
+    NetworkRuntime::current().handle
+}
+
+/// Gives the app the default runtime unless it brought its own, the plugins hand it to each transport they start.
+pub(crate) fn init_network_runtime(app: &mut App) {
+    if !app.world().contains_resource::<NetworkRuntime>() {
+        app.insert_resource(NetworkRuntime::current());
+    }
+}
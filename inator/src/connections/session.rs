@@ -0,0 +1,252 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::{HashMap, HashSet};
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::IpAddr;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::platform::time::Instant;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use message_derive::Message;
+use crate::systems::messaging::{MessageDirection, MessageTrait};
+use crate::systems::protocol::ProtocolInfo;
+
+/// What a client keeps to claim its session back after a reconnect.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ResumeToken {
+    pub uuid: Uuid,
+    pub token: Uuid
+}
+
+/// First message of every client link, the server answers it with `ConnectedMessage`.
+#[derive(Serialize, Deserialize, Message, Clone)]
+#[message(direction = ClientToServer)]
+pub(crate) struct SessionRequest {
+    pub resume: Option<ResumeToken>,
+    pub credentials: Vec<u8>,
+    pub protocol: ProtocolInfo
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Session {
+    token: Uuid,
+    transport_id: Option<Uuid>,
+    expires_at: Option<Instant>
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct OpenedSession {
+    pub uuid: Uuid,
+    pub token: Uuid,
+    pub resumed: bool,
+    /// The link the session was still attached to, it has to be closed.
+    pub replaced: Option<Uuid>
+}
+
+/// Maps the ids transports give to each link to the client uuids seen by the rest of the crate,
+/// so a client coming back on a new link keeps its uuid.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub(crate) struct ServerSessions {
+    pub(crate) grace_period: Duration,
+    by_transport: HashMap<Uuid, Uuid>,
+    sessions: HashMap<Uuid, Session>
+}
+
+/// How long a link closed with a reason stays open, so the reason reaches the client first.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const DISCONNECT_LINGER: Duration = Duration::from_secs(1);
+
+/// Checked when a link is accepted for addresses, and when a session is resumed for uuids.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub(crate) struct BanList {
+    pub(crate) clients: HashSet<Uuid>,
+    pub(crate) addresses: HashSet<IpAddr>
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ServerSessions {
+    pub(crate) fn open(&mut self, transport_id: Uuid, resume: Option<ResumeToken>) -> OpenedSession {
+        let token = Uuid::new_v4();
+
+        if let Some(resume) = resume
+            && let Some(session) = self.sessions.get_mut(&resume.uuid)
+            && session.token == resume.token {
+            let replaced = session.transport_id.replace(transport_id);
+
+            if let Some(replaced) = replaced {
+                self.by_transport.remove(&replaced);
+            }
+
+            session.token = token;
+            session.expires_at = None;
+            self.by_transport.insert(transport_id, resume.uuid);
+
+            return OpenedSession {
+                uuid: resume.uuid,
+                token,
+                resumed: true,
+                replaced
+            };
+        }
+
+        self.sessions.insert(transport_id, Session {
+            token,
+            transport_id: Some(transport_id),
+            expires_at: None
+        });
+        self.by_transport.insert(transport_id, transport_id);
+
+        OpenedSession {
+            uuid: transport_id,
+            token,
+            resumed: false,
+            replaced: None
+        }
+    }
+
+    pub(crate) fn session_of(&self, transport_id: &Uuid) -> Option<Uuid> {
+        self.by_transport.get(transport_id).copied()
+    }
+
+    pub(crate) fn transport_of(&self, client: &Uuid) -> Option<Uuid> {
+        self.sessions.get(client).and_then(|session| session.transport_id)
+    }
+
+    /// Returns the client to report as disconnected now, sessions are kept for
+    /// `grace_period` before that.
+    pub(crate) fn link_dropped(&mut self, transport_id: &Uuid) -> Option<Uuid> {
+        let client = self.by_transport.remove(transport_id)?;
+
+        if self.grace_period.is_zero() {
+            self.sessions.remove(&client);
+            return Some(client);
+        }
+
+        if let Some(session) = self.sessions.get_mut(&client) {
+            session.transport_id = None;
+            session.expires_at = Some(Instant::now() + self.grace_period);
+        }
+
+        None
+    }
+
+    /// Ends a session without a grace period, returns false when there is no such session.
+    pub(crate) fn close(&mut self, client: &Uuid) -> bool {
+        let Some(session) = self.sessions.remove(client) else {
+            return false;
+        };
+
+        if let Some(transport_id) = session.transport_id {
+            self.by_transport.remove(&transport_id);
+        }
+
+        true
+    }
+
+    pub(crate) fn expired(&mut self) -> Vec<Uuid> {
+        let now = Instant::now();
+        let expired: Vec<Uuid> = self.sessions.iter()
+            .filter(|(_, session)| session.expires_at.is_some_and(|expires_at| now >= expires_at))
+            .map(|(client, _)| *client)
+            .collect();
+
+        for client in &expired {
+            self.sessions.remove(client);
+        }
+
+        expired
+    }
+
+    pub(crate) fn clients(&self) -> Vec<Uuid> {
+        self.sessions.iter()
+            .filter(|(_, session)| session.transport_id.is_some())
+            .map(|(client, _)| *client)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions(grace_period: Duration) -> ServerSessions {
+        ServerSessions {
+            grace_period,
+            ..Default::default()
+        }
+    }
+
+    fn token(opened: &OpenedSession) -> Option<ResumeToken> {
+        Some(ResumeToken { uuid: opened.uuid, token: opened.token })
+    }
+
+    #[test]
+    fn dropped_link_resumes_within_the_grace_period() {
+        let mut sessions = sessions(Duration::from_secs(60));
+        let first = sessions.open(Uuid::new_v4(), None);
+
+        assert_eq!(sessions.link_dropped(&first.uuid), None);
+        assert!(sessions.clients().is_empty());
+
+        let link = Uuid::new_v4();
+        let resumed = sessions.open(link, token(&first));
+
+        assert!(resumed.resumed);
+        assert_eq!(resumed.uuid, first.uuid);
+        assert_ne!(resumed.token, first.token);
+        assert_eq!(sessions.session_of(&link), Some(first.uuid));
+        assert_eq!(sessions.transport_of(&first.uuid), Some(link));
+        assert!(sessions.expired().is_empty());
+    }
+
+    #[test]
+    fn used_or_wrong_tokens_open_a_new_session() {
+        let mut sessions = sessions(Duration::from_secs(60));
+        let first = sessions.open(Uuid::new_v4(), None);
+
+        sessions.link_dropped(&first.uuid);
+        sessions.open(Uuid::new_v4(), token(&first));
+
+        let reused = sessions.open(Uuid::new_v4(), token(&first));
+
+        assert!(!reused.resumed);
+        assert_ne!(reused.uuid, first.uuid);
+    }
+
+    #[test]
+    fn resuming_a_live_session_replaces_its_link() {
+        let mut sessions = sessions(Duration::from_secs(60));
+        let first_link = Uuid::new_v4();
+        let first = sessions.open(first_link, None);
+
+        let resumed = sessions.open(Uuid::new_v4(), token(&first));
+
+        assert_eq!(resumed.replaced, Some(first_link));
+        assert_eq!(sessions.session_of(&first_link), None);
+        assert_eq!(sessions.clients(), vec![first.uuid]);
+    }
+
+    #[test]
+    fn sessions_expire_after_the_grace_period() {
+        let mut sessions = sessions(Duration::from_millis(10));
+        let first = sessions.open(Uuid::new_v4(), None);
+
+        sessions.link_dropped(&first.uuid);
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(sessions.expired(), vec![first.uuid]);
+        assert!(!sessions.open(Uuid::new_v4(), token(&first)).resumed);
+    }
+
+    #[test]
+    fn without_a_grace_period_dropped_links_end_the_session() {
+        let mut sessions = sessions(Duration::ZERO);
+        let first = sessions.open(Uuid::new_v4(), None);
+
+        assert_eq!(sessions.link_dropped(&first.uuid), Some(first.uuid));
+        assert!(!sessions.open(Uuid::new_v4(), token(&first)).resumed);
+    }
+}
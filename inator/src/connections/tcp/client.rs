@@ -2,28 +2,41 @@
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::net::{TcpStream};
-use tokio::runtime::Runtime;
+use tokio::runtime::Handle;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::TlsStream;
 use tokio_util::sync::CancellationToken;
-use crate::connections::{BytesOptions, Connection, OrderOptions};
-use crate::connections::tcp::connection::TcpConnection;
+use crate::connections::{BytesOptions, Connection, ConnectionsType, MessageChannel, OrderOptions};
+use crate::connections::compression::{Compression, CompressionStats};
+use crate::connections::tcp::connection::{TcpConnection, TcpTransportStream, DEFAULT_MAX_FRAME_SIZE};
+use crate::connections::tcp::tls::{tls_connector, ClientTcpTls};
+use crate::connections::transport::{ClientTransport, TransportState};
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
 
 pub struct ClientTcpSettings {
     pub(crate) address: IpAddr,
     pub(crate) port: u16,
     pub(crate) bytes: BytesOptions,
-    pub(crate) order: OrderOptions
+    pub(crate) order: OrderOptions,
+    pub(crate) server_name: String,
+    pub(crate) max_frame_size: usize,
+    pub(crate) compression: Compression,
+    pub(crate) compression_threshold: usize,
+    pub(crate) tls: Option<ClientTcpTls>
 }
 pub struct ClientTcpConnection {
     pub(crate) settings: ClientTcpSettings,
     pub(crate) name: &'static str,
     pub(crate) started: bool,
-    pub(crate) runtime: Option<Runtime>,
+    pub(crate) runtime: Option<Handle>,
     pub(crate) dropped: Arc<AtomicBool>,
     pub(crate) local_tcp_connection: Option<TcpConnection>,
     pub(crate) cancel_token: Arc<CancellationToken>,
-    pub(crate) connection_up_sender: Arc<UnboundedSender<TcpStream>>,
-    pub(crate) connection_up_receiver:  UnboundedReceiver<TcpStream>
+    pub(crate) connection_up_sender: Arc<UnboundedSender<TcpTransportStream>>,
+    pub(crate) connection_up_receiver:  UnboundedReceiver<TcpTransportStream>
 }
 
 impl Default for ClientTcpSettings {
@@ -32,7 +45,12 @@ impl Default for ClientTcpSettings {
             address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
             port: 8080,
             bytes: BytesOptions::U32,
-            order: OrderOptions::LittleEndian
+            order: OrderOptions::LittleEndian,
+            server_name: "localhost".to_string(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            compression: Compression::None,
+            compression_threshold: 0,
+            tls: None
         }
     }
 }
@@ -43,20 +61,57 @@ impl ClientTcpSettings {
             address,
             port,
             bytes,
-            order
+            order,
+            ..Default::default()
         }
     }
+
+    /// Frames announcing more bytes than this are rejected and the peer is disconnected,
+    /// by default `DEFAULT_MAX_FRAME_SIZE`.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Compresses frames of at least `threshold` bytes when the peer picked the same algorithm,
+    /// by default frames are sent uncompressed.
+    pub fn with_compression(mut self, compression: Compression, threshold: usize) -> Self {
+        self.compression = compression;
+        self.compression_threshold = threshold;
+        self
+    }
+
+    pub fn with_server_name(mut self, server_name: &str) -> Self {
+        self.server_name = server_name.to_string();
+        self
+    }
+
+    pub fn with_trusted_certificate(mut self, certificate: CertificateDer<'static>) -> Self {
+        self.tls = Some(ClientTcpTls::TrustedCertificate(certificate));
+        self
+    }
+
+    /// Pins the SHA-256 fingerprint of the server certificate, see `certificate_fingerprint`.
+    pub fn with_pinned_fingerprint(mut self, fingerprint: [u8; 32]) -> Self {
+        self.tls = Some(ClientTcpTls::PinnedFingerprint(fingerprint));
+        self
+    }
+
+    pub fn with_unverified_tls(mut self) -> Self {
+        self.tls = Some(ClientTcpTls::AcceptAnyCertificate);
+        self
+    }
 }
 
 impl ClientTcpConnection {
     pub fn new(settings: ClientTcpSettings, name: &'static str) -> ClientTcpConnection {
-        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<TcpStream>();
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<TcpTransportStream>();
 
         ClientTcpConnection {
             settings,
             name,
             started: false,
-            runtime: Some(Runtime::new().unwrap()),
+            runtime: None,
             dropped: Arc::new(AtomicBool::new(false)),
             local_tcp_connection: None,
             cancel_token: Arc::new(CancellationToken::new()),
@@ -72,29 +127,57 @@ impl Connection for ClientTcpConnection {
 
         let settings = &self.settings;
         let address = (settings.address, settings.port);
+        let tls = match &settings.tls {
+            Some(tls) => {
+                let Some(tls_connector) = tls_connector(tls) else {
+                    return;
+                };
+
+                let server_name = match ServerName::try_from(settings.server_name.clone()) {
+                    Ok(server_name) => server_name,
+                    Err(e) => {
+                        eprintln!("Invalid server name: {:?}", e);
+                        return;
+                    }
+                };
+
+                Some((tls_connector, server_name))
+            }
+            None => None,
+        };
         let dropped = Arc::clone(&self.dropped);
+        let cancel_token = Arc::clone(&self.cancel_token);
         let connection_up_sender = Arc::clone(&self.connection_up_sender);
 
         self.started = true;
+        self.dropped.store(false, Ordering::SeqCst);
 
-        self.runtime.as_ref().unwrap().spawn(async move {
-            dropped.store(false, Ordering::SeqCst);
+        self.runtime.get_or_insert_with(network_runtime).spawn(async move {
+            let stream = match TcpStream::connect(address).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("Failed to connect to server: {}", e);
+                    dropped.store(true, Ordering::SeqCst);
+                    return;
+                }
+            };
 
-            let tcp_stream = loop {
-                match TcpStream::connect(address).await {
-                    Ok(stream) => break stream,
+            let tcp_stream = match tls {
+                Some((tls_connector, server_name)) => match tls_connector.connect(server_name, stream).await {
+                    Ok(tls_stream) => TcpTransportStream::Tls(Box::new(TlsStream::Client(tls_stream))),
                     Err(e) => {
-                        println!("Failed to connect to server: {}", e);
-
-                        if dropped.load(Ordering::SeqCst) {
-                            return;
-                        }
-
-                        continue;
+                        println!("Tls handshake with server failed: {}", e);
+                        dropped.store(true, Ordering::SeqCst);
+                        return;
                     }
-                }
+                },
+                None => TcpTransportStream::Plain(stream),
             };
 
+            if cancel_token.is_cancelled() {
+                return;
+            }
+
             connection_up_sender.send(tcp_stream).unwrap();
         });
     }
@@ -109,14 +192,13 @@ impl Connection for ClientTcpConnection {
         }
 
         self.cancel_token.cancel();
+        self.cancel_token = Arc::new(CancellationToken::new());
         self.dropped.store(true,Ordering::SeqCst);
         self.started = false;
     }
 
     fn disconnect(&mut self) {
-        if let Some(runtime) = self.runtime.take() {
-            runtime.shutdown_background();
-        }
+        self.runtime.take();
 
         if let Some(local_tcp_connection) = self.local_tcp_connection.take() {
             drop(local_tcp_connection);
@@ -125,4 +207,93 @@ impl Connection for ClientTcpConnection {
         self.cancel_token.cancel();
         self.dropped.store(true, Ordering::SeqCst);
     }
-}
\ No newline at end of file
+}
+
+impl ClientTransport for ClientTcpConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Tcp
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.local_tcp_connection.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok(tcp_stream) = self.connection_up_receiver.try_recv() {
+            if let Some(local_tcp_connection) = self.local_tcp_connection.take() {
+                drop(local_tcp_connection);
+            }
+
+            let settings = &self.settings;
+            let mut tcp_connection = TcpConnection::new(tcp_stream, self.name, NetworkSide::Client, Arc::clone(&self.cancel_token),settings.bytes,settings.order,settings.max_frame_size)
+                .with_compression(settings.compression, settings.compression_threshold);
+
+            tcp_connection.start_listening(self.runtime.as_ref().unwrap());
+
+            self.local_tcp_connection = Some(tcp_connection);
+        } else if self.started && self.local_tcp_connection.is_none() && self.dropped.load(Ordering::SeqCst) {
+            self.started = false;
+        }
+
+        let connection_down = match self.local_tcp_connection.as_mut() {
+            Some(local_tcp_connection) => local_tcp_connection.connection_down_receiver.try_recv().is_ok(),
+            None => false
+        };
+
+        if connection_down {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>> {
+        let mut received = Vec::new();
+
+        if let Some(local_tcp_connection) = self.local_tcp_connection.as_mut() {
+            while let Ok(message) = local_tcp_connection.message_received_receiver.try_recv() {
+                received.push(message);
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+        match self.local_tcp_connection.as_mut() {
+            Some(local_tcp_connection) => {
+                local_tcp_connection.send_message(message);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn queue_depth(&self) -> Option<usize> {
+        self.local_tcp_connection.as_ref().map(|local_tcp_connection| local_tcp_connection.queue_depth())
+    }
+
+    fn compression_stats(&self) -> Option<CompressionStats> {
+        self.local_tcp_connection.as_ref().map(|local_tcp_connection| local_tcp_connection.compression_stats())
+    }
+
+    fn cancel(&mut self) {
+        self.cancel_connection()
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
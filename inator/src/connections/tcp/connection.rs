@@ -1,25 +1,42 @@
 ﻿use std::net::SocketAddr;
 use std::sync::Arc;
-use bincode::config::standard;
-use bincode::serde::encode_to_vec;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use tokio::io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio::runtime::Runtime;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::runtime::Handle;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::{Mutex};
+use tokio_rustls::TlsStream;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use crate::connections::{BytesOptions, OrderOptions};
-use crate::connections::tcp::reader_writer::{read_from_settings, read_value_to_usize, value_from_number, write_from_settings};
+use crate::connections::compression::{decompress, Compression, CompressionCounters, CompressionStats, FRAME_HELLO, FRAME_RAW};
+use crate::connections::tcp::reader_writer::{frame_size_fits, read_from_settings, read_value_to_usize, value_from_number, write_from_settings};
 use crate::NetworkSide;
-use crate::systems::messaging::{deserialize_message, MessageTrait};
+use crate::systems::messaging::{deserialize_message, encode_message, MessageTrait};
+
+/// Largest frame a connection accepts unless its settings say otherwise.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+pub type TcpReadHalf = Box<dyn AsyncRead + Send + Unpin>;
+pub type TcpWriteHalf = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// TLS wraps the stream before it is split, so the framing on top of it is the same for both.
+pub enum TcpTransportStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(UnixStream)
+}
 
 pub struct TcpConnection {
-    pub read_half: Option<Arc<Mutex<OwnedReadHalf>>>,
-    pub write_half: Option<Arc<Mutex<OwnedWriteHalf>>>,
+    pub read_half: Option<Arc<Mutex<TcpReadHalf>>>,
+    pub write_half: Option<Arc<Mutex<TcpWriteHalf>>>,
     pub connection_name: &'static str,
-    pub socket_addr: SocketAddr,
+    /// `None` for Unix sockets, which have no network address.
+    pub socket_addr: Option<SocketAddr>,
     pub network_side: NetworkSide,
     pub uuid: Option<Uuid>,
     pub cancellation_token: Arc<CancellationToken>,
@@ -27,9 +44,35 @@ pub struct TcpConnection {
     pub connection_down_receiver: UnboundedReceiver<()>,
     pub message_received_sender: Arc<UnboundedSender<Box<dyn MessageTrait>>>,
     pub message_received_receiver: UnboundedReceiver<Box<dyn MessageTrait>>,
+    /// Written once when the peer sends a size prefix that is invalid or over `max_frame_size`,
+    /// or a frame that can not be decompressed, the connection stops reading after it.
+    pub frame_rejected_sender: Arc<UnboundedSender<()>>,
+    pub frame_rejected_receiver: UnboundedReceiver<()>,
     pub bytes: BytesOptions,
     pub order: OrderOptions,
-    pub listening: bool
+    pub max_frame_size: usize,
+    pub compression: Compression,
+    /// Frames smaller than this are sent uncompressed.
+    pub compression_threshold: usize,
+    pub listening: bool,
+    pub(crate) outgoing_sender: Option<UnboundedSender<Vec<u8>>>,
+    pub(crate) outgoing_receiver: Option<UnboundedReceiver<Vec<u8>>>,
+    pub(crate) queued: Arc<AtomicUsize>,
+    /// The kind of `Compression` the peer announced, frames are only compressed when it matches ours.
+    pub(crate) peer_compression: Arc<AtomicU8>,
+    pub(crate) compression_counters: Arc<CompressionCounters>
+}
+
+fn report_read_error(e: &std::io::Error, what: &str, network_side: NetworkSide) {
+    eprintln!("Failed to read {}: {:?}", what, e);
+    eprintln!("From {:?}", network_side);
+
+    match e.kind() {
+        std::io::ErrorKind::ConnectionAborted => println!("Connection aborted (network down or aborted by OS)"),
+        std::io::ErrorKind::Other => println!("Connection was probably closed manually"),
+        std::io::ErrorKind::UnexpectedEof => println!("Connection was closed by the peer"),
+        _ => println!("Unexpected error: {:?}", e.kind())
+    }
 }
 
 impl Drop for TcpConnection {
@@ -40,13 +83,32 @@ impl Drop for TcpConnection {
 }
 
 impl TcpConnection {
-    pub fn new(tcp_stream: TcpStream, connection_name: &'static str, network_side: NetworkSide, cancellation_token: Arc<CancellationToken>, bytes: BytesOptions, order: OrderOptions) -> Self {
-        let socket_addr = tcp_stream.peer_addr().unwrap();
+    pub fn new(tcp_stream: TcpTransportStream, connection_name: &'static str, network_side: NetworkSide, cancellation_token: Arc<CancellationToken>, bytes: BytesOptions, order: OrderOptions, max_frame_size: usize) -> Self {
         let (connection_down_sender, connection_down_receiver) = unbounded_channel::<()>();
+        let (frame_rejected_sender, frame_rejected_receiver) = unbounded_channel::<()>();
+        let (outgoing_sender, outgoing_receiver) = unbounded_channel::<Vec<u8>>();
         let (message_received_sender, message_received_receiver) = unbounded_channel::<Box<dyn MessageTrait>>();
-        let (read_half, write_half) = match tcp_stream.into_split() {
-            (read_half, write_half) => (Some(Arc::new(Mutex::new(read_half))), Some(Arc::new(Mutex::new(write_half)))),
+        let (socket_addr, read_half, write_half): (Option<SocketAddr>, TcpReadHalf, TcpWriteHalf) = match tcp_stream {
+            TcpTransportStream::Plain(tcp_stream) => {
+                let socket_addr = tcp_stream.peer_addr().ok();
+                let (read_half, write_half) = tcp_stream.into_split();
+
+                (socket_addr, Box::new(read_half), Box::new(write_half))
+            }
+            TcpTransportStream::Tls(tls_stream) => {
+                let socket_addr = tls_stream.get_ref().0.peer_addr().ok();
+                let (read_half, write_half) = split(*tls_stream);
+
+                (socket_addr, Box::new(read_half), Box::new(write_half))
+            }
+            #[cfg(unix)]
+            TcpTransportStream::Unix(unix_stream) => {
+                let (read_half, write_half) = unix_stream.into_split();
+
+                (None, Box::new(read_half), Box::new(write_half))
+            }
         };
+        let (read_half, write_half) = (Some(Arc::new(Mutex::new(read_half))), Some(Arc::new(Mutex::new(write_half))));
 
         TcpConnection {
             read_half,
@@ -60,13 +122,32 @@ impl TcpConnection {
             connection_down_receiver,
             message_received_sender: Arc::new(message_received_sender),
             message_received_receiver,
+            frame_rejected_sender: Arc::new(frame_rejected_sender),
+            frame_rejected_receiver,
             bytes,
             order,
-            listening: false
+            max_frame_size,
+            compression: Compression::None,
+            compression_threshold: 0,
+            listening: false,
+            outgoing_sender: Some(outgoing_sender),
+            outgoing_receiver: Some(outgoing_receiver),
+            queued: Arc::new(AtomicUsize::new(0)),
+            peer_compression: Arc::new(AtomicU8::new(FRAME_RAW)),
+            compression_counters: Arc::new(CompressionCounters::default())
         }
     }
 
-    pub fn start_listening(&mut self, runtime: &Runtime) {
+    /// Must be set before `start_listening`, the first frame written announces it to the peer.
+    pub fn with_compression(mut self, compression: Compression, compression_threshold: usize) -> Self {
+        self.compression = compression;
+        self.compression_threshold = compression_threshold;
+        self
+    }
+
+    pub fn start_listening(&mut self, runtime: &Handle) {
+        self.start_writing(runtime);
+
         let read_half = match &self.read_half {
             Some(read_half) => Arc::clone(read_half),
             None => return,
@@ -74,130 +155,202 @@ impl TcpConnection {
 
         let connection_down_sender = Arc::clone(&self.connection_down_sender);
         let message_received_sender = Arc::clone(&self.message_received_sender);
+        let frame_rejected_sender = Arc::clone(&self.frame_rejected_sender);
         let bytes_options = self.bytes;
         let order_options = self.order;
+        let max_frame_size = self.max_frame_size;
+        let peer_compression = Arc::clone(&self.peer_compression);
+        let compression_counters = Arc::clone(&self.compression_counters);
         let cancellation_token = Arc::clone(&self.cancellation_token);
         let network_side = self.network_side;
 
         self.listening = true;
 
         runtime.spawn(async move {
-            loop {
-                tokio::select! {
-                    _ = cancellation_token.cancelled() => break,
+            // Cancelling has to interrupt a read in the middle of a frame too.
+            let reader_token = Arc::clone(&cancellation_token);
 
-                    mut guard = read_half.lock() => {
-                        let size_value = match read_from_settings(&mut guard, &bytes_options, &order_options).await {
-                            Ok(v) => v,
+            reader_token.run_until_cancelled(async move {
+                loop {
+                    tokio::select! {
+                        _ = cancellation_token.cancelled() => break,
 
-                            Err(e) => {
-                                eprintln!("Failed to read size: {:?}", e);
-                                eprintln!("From {:?}", network_side);
+                        mut guard = read_half.lock() => {
+                            let size_value = match read_from_settings(&mut guard, &bytes_options, &order_options).await {
+                                Ok(v) => v,
 
-                                match e.kind() {
-                                    std::io::ErrorKind::ConnectionAborted => {
+                                Err(e) => {
+                                    report_read_error(&e, "size", network_side);
 
-                                        println!("Connection aborted (network down or aborted by OS)");
+                                    let _ = connection_down_sender.send(());
 
-                                        connection_down_sender.send(()).unwrap();
+                                    break;
+                                }
+                            };
 
-                                        break;
-                                    },
-                                    std::io::ErrorKind::Other => {
-                                        println!("Connection was probably closed manually");
+                            let size = match read_value_to_usize(size_value) {
+                                Some(size) if size <= max_frame_size => size,
+                                _ => {
+                                    eprintln!("Rejected frame with size {:?}, the limit is {}", size_value, max_frame_size);
+                                    eprintln!("From {:?}", network_side);
+
+                                    // The stream can not be read past a bad prefix, the server closes the
+                                    // link with a reason and a client just drops it.
+                                    if network_side == NetworkSide::Client {
+                                        let _ = connection_down_sender.send(());
+                                    } else {
+                                        let _ = frame_rejected_sender.send(());
+                                    }
 
-                                        connection_down_sender.send(()).unwrap();
+                                    break;
+                                }
+                            };
 
-                                        break;
-                                    },
+                            let mut buf = vec![0u8; size];
 
-                                    _ => {
-                                        println!("Unexpected error: {:?}", e.kind());
-                                    }
-                                }
+                            // A frame cut short can not be parsed, whatever the error was.
+                            if let Err(e) = guard.read_exact(&mut buf).await {
+                                report_read_error(&e, "frame", network_side);
+
+                                let _ = connection_down_sender.send(());
 
                                 break;
                             }
-                        };
 
-                        let size = read_value_to_usize(size_value);
+                            if let [FRAME_HELLO, kind] = buf[..] {
+                                peer_compression.store(kind, Ordering::Relaxed);
+                                continue;
+                            }
 
-                        let mut buf = vec![0u8; size];
+                            let Some(payload) = buf.split_first().and_then(|(kind, data)| decompress(*kind, data, max_frame_size)) else {
+                                eprintln!("Rejected frame that could not be decompressed");
+                                eprintln!("From {:?}", network_side);
 
-                        if let Err(e) = guard.read_exact(&mut buf).await {
-                            eprintln!("Failed to read size: {:?}", e);
-                            eprintln!("From {:?}", network_side);
+                                if network_side == NetworkSide::Client {
+                                    let _ = connection_down_sender.send(());
+                                } else {
+                                    let _ = frame_rejected_sender.send(());
+                                }
+
+                                break;
+                            };
 
-                            match e.kind() {
+                            compression_counters.received(payload.len(), buf.len());
 
-                                std::io::ErrorKind::ConnectionAborted => {
+                            if let Some(message) = deserialize_message(&payload) {
+                                let _ = message_received_sender.send(message);
+                            } else {
+                                eprintln!("Message not registered or failed to deserialize client");
+                            }
+                        }
+                    }
+                }
+            }).await;
+        });
+    }
 
-                                    println!("Connection aborted (network down or aborted by OS)");
+    /// Spawns the task that owns the write half. Frames are written one after the other in the order
+    /// they were queued, and the write half is shut down once the queue is closed and drained.
+    /// The first frame announces our `Compression` to the peer.
+    fn start_writing(&mut self, runtime: &Handle) {
+        let (Some(write_half), Some(mut outgoing_receiver)) = (self.write_half.as_ref().map(Arc::clone), self.outgoing_receiver.take()) else {
+            return;
+        };
 
-                                    connection_down_sender.send(()).unwrap();
+        let bytes_options = self.bytes;
+        let order_options = self.order;
+        let max_frame_size = self.max_frame_size;
+        let cancellation_token = Arc::clone(&self.cancellation_token);
+        let queued = Arc::clone(&self.queued);
+        let compression = self.compression;
+        let compression_threshold = self.compression_threshold;
+        let peer_compression = Arc::clone(&self.peer_compression);
+        let compression_counters = Arc::clone(&self.compression_counters);
+        let network_side = self.network_side;
 
+        runtime.spawn(async move {
+            let mut guard = write_half.lock().await;
+            let mut hello = (compression != Compression::None).then(|| vec![FRAME_HELLO, compression.kind()]);
 
-                                    break;
-                                },
+            loop {
+                let framed = if let Some(hello) = hello.take() {
+                    hello
+                } else {
+                    let encoded = tokio::select! {
+                        _ = cancellation_token.cancelled() => break,
+                        encoded = outgoing_receiver.recv() => match encoded {
+                            Some(encoded) => encoded,
+                            None => break
+                        }
+                    };
 
-                                std::io::ErrorKind::Other => {
+                    queued.fetch_sub(1, Ordering::Relaxed);
 
-                                    println!("Connection was probably closed manually");
+                    let framed = if peer_compression.load(Ordering::Relaxed) == compression.kind() {
+                        compression.frame(&encoded, compression_threshold)
+                    } else {
+                        Compression::None.frame(&encoded, compression_threshold)
+                    };
 
-                                    connection_down_sender.send(()).unwrap();
+                    compression_counters.sent(encoded.len(), framed.len());
+                    framed
+                };
 
+                // The peer would reject it, and a prefix type too small for it would wrap around.
+                if framed.len() > max_frame_size || !frame_size_fits(framed.len(), bytes_options) {
+                    eprintln!("Dropped outgoing frame of {} bytes, the limit is {} and the prefix is {:?}", framed.len(), max_frame_size, bytes_options);
+                    eprintln!("From {:?}", network_side);
+                    continue;
+                }
 
-                                    break;
-                                },
+                let size_value = value_from_number(framed.len() as f64, bytes_options);
 
-                                _ => {
-                                    println!("Unexpected error: {:?}", e.kind());
-                                }
-                            }
-                        }
+                if let Err(e) = write_from_settings(&mut guard, &size_value, &order_options).await {
+                    eprintln!("Failed to send size: {:?}", e);
+                    eprintln!("From {:?}", network_side);
+                    break;
+                }
 
-                        if let Some(message) = deserialize_message(&buf) {
-                            message_received_sender.send(message).unwrap();
-                        } else {
-                            eprintln!("Message not registered or failed to deserialize client");
-                        }
-                    }
+                if let Err(e) = guard.write_all(&framed).await {
+                    eprintln!("Failed to send message: {:?}", e);
+                    eprintln!("From {:?}", network_side);
+                    break;
                 }
             }
+
+            let _ = guard.shutdown().await;
         });
     }
 
-    pub fn send_message(&mut self, message: &dyn MessageTrait, runtime: &Runtime){
-        let write_half = match &self.write_half {
-            Some(write_half) => Arc::clone(write_half),
-            None => return,
+    pub fn send_message(&mut self, message: &dyn MessageTrait){
+        let Some(outgoing_sender) = &self.outgoing_sender else {
+            return;
         };
 
-        let bytes_options = self.bytes;
-        let order_options = self.order;
-        let config = standard();
-        let encoded = encode_to_vec(&message, config).unwrap();
-        let message_size = encoded.len();
-        let network_side = self.network_side;
+        let Some(encoded) = encode_message(message) else {
+            return;
+        };
 
-        runtime.spawn(async move {
-            let mut guard = write_half.lock().await;
+        self.queued.fetch_add(1, Ordering::Relaxed);
 
-            let size_value = value_from_number(message_size as f64, bytes_options);
+        if outgoing_sender.send(encoded).is_err() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            eprintln!("Tcp writer is closed");
+        }
+    }
 
-            if let Err(e) = write_from_settings(&mut guard, &size_value, &order_options).await {
-                eprintln!("Failed to send size: {:?}", e);
-                eprintln!("From {:?}", network_side);
-                return;
-            }
+    /// Frames queued by `send_message` that the writer task has not taken yet.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
 
-            if let Err(e) = guard.write_all(&encoded).await {
-                eprintln!("Failed to send message: {:?}", e);
-                eprintln!("From {:?}", network_side);
-                return;
-            }
+    /// Bytes sent and received before and after compression, counted from the frame bodies.
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression_counters.stats()
+    }
 
-            //println!("Message sent for client");
-        });
+    /// Closes the queue, the writer shuts the stream down after sending what was already queued.
+    pub fn shutdown(&mut self) {
+        self.outgoing_sender.take();
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
 ﻿pub mod server;
 pub mod client;
 pub mod connection;
-mod reader_writer;
\ No newline at end of file
+mod reader_writer;
+pub mod tls;
\ No newline at end of file
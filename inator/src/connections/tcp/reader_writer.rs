@@ -1,30 +1,44 @@
 ﻿use std::io::Error;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use crate::connections::tcp::connection::{TcpReadHalf, TcpWriteHalf};
 use crate::connections::{BytesOptions, OrderOptions, ReadValue};
 
-pub fn read_value_to_usize(value: ReadValue) -> usize {
+/// Returns `None` for sizes no frame can have: negative, fractional, NaN or bigger than a `usize`.
+pub fn read_value_to_usize(value: ReadValue) -> Option<usize> {
     match value {
         // Unsigned
-        ReadValue::U8(v) => v as usize,
-        ReadValue::U16(v) => v as usize,
-        ReadValue::U32(v) => v as usize,
-        ReadValue::U64(v) => v as usize,
-        ReadValue::U128(v) => v as usize,
+        ReadValue::U8(v) => Some(v as usize),
+        ReadValue::U16(v) => Some(v as usize),
+        ReadValue::U32(v) => usize::try_from(v).ok(),
+        ReadValue::U64(v) => usize::try_from(v).ok(),
+        ReadValue::U128(v) => usize::try_from(v).ok(),
 
         // Signed
-        ReadValue::I8(v) => v as usize,
-        ReadValue::I16(v) => v as usize,
-        ReadValue::I32(v) => v as usize,
-        ReadValue::I64(v) => v as usize,
-        ReadValue::I128(v) => v as usize,
+        ReadValue::I8(v) => usize::try_from(v).ok(),
+        ReadValue::I16(v) => usize::try_from(v).ok(),
+        ReadValue::I32(v) => usize::try_from(v).ok(),
+        ReadValue::I64(v) => usize::try_from(v).ok(),
+        ReadValue::I128(v) => usize::try_from(v).ok(),
 
         // Floats
-        ReadValue::F32(v) => v as usize,
-        ReadValue::F64(v) => v as usize,
+        ReadValue::F32(v) => float_to_usize(v as f64),
+        ReadValue::F64(v) => float_to_usize(v),
     }
 }
 
+fn float_to_usize(v: f64) -> Option<usize> {
+    if v.is_finite() && v >= 0.0 && v.fract() == 0.0 && v <= usize::MAX as f64 {
+        Some(v as usize)
+    } else {
+        None
+    }
+}
+
+/// Whether a prefix of this type can hold `size` exactly, `value_from_number` saturates otherwise.
+pub fn frame_size_fits(size: usize, bytes: BytesOptions) -> bool {
+    read_value_to_usize(value_from_number(size as f64, bytes)) == Some(size)
+}
+
 pub fn value_from_number(number: f64, bytes: BytesOptions) -> ReadValue {
     match bytes {
         // Unsigned
@@ -48,7 +62,7 @@ pub fn value_from_number(number: f64, bytes: BytesOptions) -> ReadValue {
 }
 
 pub async fn write_from_settings(
-    write_half: &mut OwnedWriteHalf,
+    write_half: &mut TcpWriteHalf,
     value: &ReadValue,
     order: &OrderOptions,
 ) -> Result<(), Error> {
@@ -106,7 +120,7 @@ pub async fn write_from_settings(
 }
 
 pub async fn read_from_settings(
-    read_half: &mut OwnedReadHalf,
+    read_half: &mut TcpReadHalf,
     bytes: &BytesOptions,
     order: &OrderOptions,
 ) -> Result<ReadValue, Error> {
@@ -159,3 +173,44 @@ pub async fn read_from_settings(
 
     Ok(value)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(size: f64, bytes: BytesOptions, order: OrderOptions) -> Option<usize> {
+        let (writer, reader) = tokio::io::duplex(64);
+        let mut write_half: TcpWriteHalf = Box::new(writer);
+        let mut read_half: TcpReadHalf = Box::new(reader);
+
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+            write_from_settings(&mut write_half, &value_from_number(size, bytes), &order).await.unwrap();
+            read_value_to_usize(read_from_settings(&mut read_half, &bytes, &order).await.unwrap())
+        })
+    }
+
+    #[test]
+    fn size_prefixes_round_trip() {
+        for bytes in [BytesOptions::U16, BytesOptions::U32, BytesOptions::U64, BytesOptions::I32, BytesOptions::F64] {
+            for order in [OrderOptions::LittleEndian, OrderOptions::BigEndian] {
+                assert_eq!(round_trip(1200.0, bytes, order), Some(1200), "{:?} {:?}", bytes, order);
+            }
+        }
+    }
+
+    #[test]
+    fn impossible_sizes_are_rejected() {
+        assert_eq!(round_trip(-1.0, BytesOptions::I32, OrderOptions::LittleEndian), None);
+        assert_eq!(round_trip(1.5, BytesOptions::F32, OrderOptions::BigEndian), None);
+        assert_eq!(read_value_to_usize(ReadValue::F64(f64::NAN)), None);
+    }
+
+    #[test]
+    fn sizes_too_big_for_the_prefix_do_not_fit() {
+        assert!(frame_size_fits(255, BytesOptions::U8));
+        assert!(!frame_size_fits(256, BytesOptions::U8));
+        assert!(!frame_size_fits(40_000, BytesOptions::I16));
+        assert!(frame_size_fits(16 * 1024 * 1024, BytesOptions::F32));
+        assert!(!frame_size_fits((1 << 24) + 1, BytesOptions::F32));
+    }
+}
@@ -3,13 +3,21 @@ use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::runtime::Runtime;
+use tokio::runtime::Handle;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Semaphore;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::{TlsAcceptor, TlsStream};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
-use crate::connections::{BytesOptions, Connection, OrderOptions};
-use crate::connections::tcp::connection::TcpConnection;
+use crate::connections::{BIND_RETRY_INTERVAL, BytesOptions, Connection, ConnectionsType, MessageChannel, OrderOptions};
+use crate::connections::compression::{Compression, CompressionStats};
+use crate::connections::tcp::connection::{TcpConnection, TcpTransportStream, DEFAULT_MAX_FRAME_SIZE};
+use crate::connections::tcp::tls::{tls_acceptor, ServerTcpTls};
+use crate::connections::transport::{ServerTransport, TransportState};
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
 
 pub struct ServerTcpSettings {
     pub(crate) address: IpAddr,
@@ -17,7 +25,11 @@ pub struct ServerTcpSettings {
     pub(crate) bytes: BytesOptions,
     pub(crate) order: OrderOptions,
     pub(crate) max_connections: usize,
-    pub(crate) recuse_when_full: bool
+    pub(crate) recuse_when_full: bool,
+    pub(crate) max_frame_size: usize,
+    pub(crate) compression: Compression,
+    pub(crate) compression_threshold: usize,
+    pub(crate) tls: Option<ServerTcpTls>
 }
 
 pub struct ServerTcpConnection{
@@ -25,15 +37,15 @@ pub struct ServerTcpConnection{
     pub(crate) name: &'static str,
     pub(crate) listener: Option<Arc<TcpListener>>,
     pub(crate) started: bool,
-    pub(crate) runtime: Option<Runtime>,
+    pub(crate) runtime: Option<Handle>,
     pub(crate) dropped: Arc<AtomicBool>,
     pub(crate) cancel_token: Arc<CancellationToken>,
     pub(crate) connection_down_sender: Arc<UnboundedSender<()>>,
     pub(crate) connection_down_receiver: UnboundedReceiver<()>,
     pub(crate) connection_up_sender: Arc<UnboundedSender<Arc<TcpListener>>>,
     pub(crate) connection_up_receiver: UnboundedReceiver<Arc<TcpListener>>,
-    pub(crate) client_connected_sender: Arc<UnboundedSender<(TcpStream,SocketAddr)>>,
-    pub(crate) client_connected_receiver: UnboundedReceiver<(TcpStream,SocketAddr)>,
+    pub(crate) client_connected_sender: Arc<UnboundedSender<(TcpTransportStream,SocketAddr)>>,
+    pub(crate) client_connected_receiver: UnboundedReceiver<(TcpTransportStream,SocketAddr)>,
     pub(crate) connections: HashMap<Uuid,TcpConnection>
 }
 
@@ -45,7 +57,11 @@ impl Default for ServerTcpSettings {
             bytes: BytesOptions::U32,
             order: OrderOptions::LittleEndian,
             max_connections: 0,
-            recuse_when_full: false
+            recuse_when_full: false,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            compression: Compression::None,
+            compression_threshold: 0,
+            tls: None
         }
     }
 }
@@ -58,23 +74,74 @@ impl ServerTcpSettings {
             bytes,
             order,
             max_connections,
-            recuse_when_full
+            recuse_when_full,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            compression: Compression::None,
+            compression_threshold: 0,
+            tls: None
         }
     }
+
+    /// Frames announcing more bytes than this are rejected and the peer is disconnected,
+    /// by default `DEFAULT_MAX_FRAME_SIZE`.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Compresses frames of at least `threshold` bytes when the peer picked the same algorithm,
+    /// by default frames are sent uncompressed.
+    pub fn with_compression(mut self, compression: Compression, threshold: usize) -> Self {
+        self.compression = compression;
+        self.compression_threshold = threshold;
+        self
+    }
+
+    pub fn with_tls(mut self, cert_chain: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>) -> Self {
+        self.tls = Some(ServerTcpTls::Certificate(cert_chain, key));
+        self
+    }
+
+    /// Generates a certificate for `server_name` when the server starts and prints its fingerprint,
+    /// so clients can pin it with `ClientTcpSettings::with_pinned_fingerprint`.
+    pub fn with_self_signed_tls(mut self, server_name: &str) -> Self {
+        self.tls = Some(ServerTcpTls::SelfSigned(server_name.to_string()));
+        self
+    }
+}
+
+fn accept_client(stream: TcpStream, addr: SocketAddr, tls_acceptor: &Option<TlsAcceptor>, client_connected_sender: &Arc<UnboundedSender<(TcpTransportStream,SocketAddr)>>) {
+    let Some(tls_acceptor) = tls_acceptor.clone() else {
+        client_connected_sender.send((TcpTransportStream::Plain(stream),addr)).unwrap();
+        return;
+    };
+
+    let client_connected_sender = Arc::clone(client_connected_sender);
+
+    tokio::spawn(async move {
+        match tls_acceptor.accept(stream).await {
+            Ok(tls_stream) => {
+                let _ = client_connected_sender.send((TcpTransportStream::Tls(Box::new(TlsStream::Server(tls_stream))),addr));
+            }
+            Err(e) => {
+                eprintln!("Tls handshake failed with {}: {:?}", addr, e);
+            }
+        }
+    });
 }
 
 impl ServerTcpConnection {
     pub fn new(settings: ServerTcpSettings, name: &'static str) -> ServerTcpConnection {
         let (connection_down_sender,connection_down_receiver) = unbounded_channel::<()>();
         let (connection_up_sender, connection_up_receiver) = unbounded_channel::<Arc<TcpListener>>();
-        let (client_connected_sender, client_connected_receiver) = unbounded_channel::<(TcpStream,SocketAddr)>();
+        let (client_connected_sender, client_connected_receiver) = unbounded_channel::<(TcpTransportStream,SocketAddr)>();
 
         ServerTcpConnection {
             settings,
             name,
             listener: None,
             started: false,
-            runtime: Some(Runtime::new().unwrap()),
+            runtime: None,
             dropped: Arc::new(AtomicBool::new(false)),
             cancel_token: Arc::new(CancellationToken::new()),
             connection_down_sender: Arc::new(connection_down_sender),
@@ -93,6 +160,13 @@ impl Connection for ServerTcpConnection {
         if !self.can_start() {return;}
 
         let settings = &self.settings;
+        let tls_acceptor = match &settings.tls {
+            Some(tls) => match tls_acceptor(tls) {
+                Some(tls_acceptor) => Some(tls_acceptor),
+                None => return,
+            },
+            None => None,
+        };
         let max_connections = settings.max_connections;
         let address = (settings.address, settings.port);
         let dropped = Arc::clone(&self.dropped);
@@ -104,7 +178,7 @@ impl Connection for ServerTcpConnection {
 
         self.started = true;
 
-        self.runtime.as_ref().unwrap().spawn(async move {
+        self.runtime.get_or_insert_with(network_runtime).spawn(async move {
             dropped.store(false, Ordering::SeqCst);
 
             let tcp_listener = loop {
@@ -117,7 +191,7 @@ impl Connection for ServerTcpConnection {
                             return;
                         }
 
-                        continue;
+                        tokio::time::sleep(BIND_RETRY_INTERVAL).await;
                     }
                 };
             };
@@ -148,7 +222,7 @@ impl Connection for ServerTcpConnection {
                                                 Ok(permit) => {
                                                     println!("Accepted connection from {}", addr);
 
-                                                    client_connected_sender.send((stream,addr)).unwrap();
+                                                    accept_client(stream, addr, &tls_acceptor, &client_connected_sender);
 
                                                     drop(permit);
                                                 },
@@ -163,7 +237,7 @@ impl Connection for ServerTcpConnection {
                                                 Ok(permit) => {
                                                     println!("Accepted connection from {}", addr);
 
-                                                    client_connected_sender.send((stream,addr)).unwrap();
+                                                    accept_client(stream, addr, &tls_acceptor, &client_connected_sender);
 
                                                     drop(permit);
                                                 },
@@ -176,7 +250,7 @@ impl Connection for ServerTcpConnection {
                                     None => {
                                         println!("Accepted connection from {}", addr);
 
-                                        client_connected_sender.send((stream,addr)).unwrap();
+                                        accept_client(stream, addr, &tls_acceptor, &client_connected_sender);
                                     }
                                 }
                             },
@@ -230,9 +304,7 @@ impl Connection for ServerTcpConnection {
     }
 
     fn disconnect(&mut self) {
-        if let Some(runtime) = self.runtime.take() {
-            runtime.shutdown_background();
-        }
+        self.runtime.take();
 
         if let Some(listener) = self.listener.take() {
             drop(listener);
@@ -241,4 +313,196 @@ impl Connection for ServerTcpConnection {
         self.cancel_token.cancel();
         self.dropped.store(true, Ordering::SeqCst);
     }
-}
\ No newline at end of file
+}
+
+impl ServerTransport for ServerTcpConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Tcp
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.listener.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok(tcp_listener) = self.connection_up_receiver.try_recv() {
+            self.listener = Some(tcp_listener);
+        }
+
+        if self.connection_down_receiver.try_recv().is_ok() {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_accepted(&mut self) -> Vec<Uuid> {
+        let mut accepted = Vec::new();
+
+        while let Ok((tcp_stream,_)) = self.client_connected_receiver.try_recv() {
+            let settings = &self.settings;
+            // A token per client, so `disconnect_client` stops the tasks of that client only.
+            let mut tcp_connection = TcpConnection::new(tcp_stream, self.name, NetworkSide::Server, Arc::new(self.cancel_token.child_token()),settings.bytes,settings.order,settings.max_frame_size)
+                .with_compression(settings.compression, settings.compression_threshold);
+            let current_uuid = tcp_connection.uuid.unwrap();
+
+            tcp_connection.start_listening(self.runtime.as_ref().unwrap());
+
+            self.connections.insert(current_uuid,tcp_connection);
+            accepted.push(current_uuid);
+        }
+
+        accepted
+    }
+
+    fn poll_disconnected(&mut self) -> Vec<Uuid> {
+        let mut disconnected = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            if client_connection.connection_down_receiver.try_recv().is_ok() {
+                client_connection.listening = false;
+
+                disconnected.push(*uuid);
+            }
+        }
+
+        for uuid in disconnected.iter() {
+            self.connections.remove(uuid);
+        }
+
+        disconnected
+    }
+
+    fn poll_rejected(&mut self) -> Vec<Uuid> {
+        let mut rejected = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            if client_connection.frame_rejected_receiver.try_recv().is_ok() {
+                rejected.push(*uuid);
+            }
+        }
+
+        rejected
+    }
+
+    fn poll_received(&mut self) -> Vec<(Uuid, Box<dyn MessageTrait>)> {
+        let mut received = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            while let Ok(message) = client_connection.message_received_receiver.try_recv() {
+                received.push((*uuid, message));
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, client: &Uuid, message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+        match self.connections.get_mut(client) {
+            Some(client_connection) => {
+                client_connection.send_message(message);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn disconnect_client(&mut self, client: &Uuid) {
+        if let Some(mut client_connection) = self.connections.remove(client) {
+            client_connection.shutdown();
+            client_connection.cancellation_token.cancel();
+        }
+    }
+
+    fn clients(&self) -> Vec<Uuid> {
+        self.connections.keys().copied().collect()
+    }
+
+    fn client_address(&self, client: &Uuid) -> Option<SocketAddr> {
+        self.connections.get(client).and_then(|client_connection| client_connection.socket_addr)
+    }
+
+    fn queue_depth(&self, client: &Uuid) -> Option<usize> {
+        self.connections.get(client).map(|client_connection| client_connection.queue_depth())
+    }
+
+    fn compression_stats(&self, client: &Uuid) -> Option<CompressionStats> {
+        self.connections.get(client).map(|client_connection| client_connection.compression_stats())
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    fn wait_for<T>(mut poll: impl FnMut() -> Option<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        loop {
+            if let Some(value) = poll() {
+                return value;
+            }
+
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn disconnected_client_loses_its_socket() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut server = ServerTcpConnection::new(ServerTcpSettings::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port, BytesOptions::U32, OrderOptions::LittleEndian, 0, false), "tcp");
+
+        server.start();
+
+        let mut stream = wait_for(|| std::net::TcpStream::connect(("127.0.0.1", port)).ok());
+        let client = wait_for(|| server.poll_accepted().pop());
+
+        // The reader is now waiting for the rest of a large frame.
+        stream.write_all(&(1024 * 1024u32).to_le_bytes()).unwrap();
+        server.disconnect_client(&client);
+
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(stream.read(&mut [0u8; 16]).unwrap(), 0);
+
+        // Once the server side is closed for good, writing fails instead of feeding the reader.
+        wait_for(|| stream.write_all(&[0u8; 1024]).err());
+    }
+
+    #[test]
+    fn frame_cut_short_ends_the_session() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut server = ServerTcpConnection::new(ServerTcpSettings::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port, BytesOptions::U32, OrderOptions::LittleEndian, 0, false), "tcp");
+
+        server.start();
+
+        let mut stream = wait_for(|| std::net::TcpStream::connect(("127.0.0.1", port)).ok());
+        let client = wait_for(|| server.poll_accepted().pop());
+
+        stream.write_all(&100u32.to_le_bytes()).unwrap();
+        stream.write_all(&[0u8; 10]).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+        assert_eq!(wait_for(|| server.poll_disconnected().pop()), client);
+        assert!(server.poll_received().is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use crate::connections::tls::{certificate_fingerprint, fingerprint_to_hex, self_signed_certificate, FingerprintVerifier};
+
+pub enum ServerTcpTls {
+    SelfSigned(String),
+    Certificate(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)
+}
+
+pub enum ClientTcpTls {
+    TrustedCertificate(CertificateDer<'static>),
+    PinnedFingerprint([u8; 32]),
+    /// Encrypts the traffic without checking who is on the other side.
+    AcceptAnyCertificate
+}
+
+pub(crate) fn tls_acceptor(tls: &ServerTcpTls) -> Option<TlsAcceptor> {
+    let certificate = match tls {
+        ServerTcpTls::SelfSigned(server_name) => self_signed_certificate(server_name),
+        ServerTcpTls::Certificate(cert_chain, key) => Some((cert_chain.clone(), key.clone_key())),
+    };
+
+    let (cert_chain, key) = certificate?;
+
+    if let Some(end_entity) = cert_chain.first() {
+        println!("Server tcp tls certificate fingerprint: {}", fingerprint_to_hex(&certificate_fingerprint(end_entity)));
+    }
+
+    let server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key);
+
+    match server_config {
+        Ok(server_config) => Some(TlsAcceptor::from(Arc::new(server_config))),
+        Err(e) => {
+            eprintln!("Invalid tcp tls certificate: {:?}", e);
+            None
+        }
+    }
+}
+
+pub(crate) fn tls_connector(tls: &ClientTcpTls) -> Option<TlsConnector> {
+    let client_config = match tls {
+        ClientTcpTls::TrustedCertificate(certificate) => {
+            let mut roots = RootCertStore::empty();
+
+            if let Err(e) = roots.add(certificate.clone()) {
+                eprintln!("Invalid trusted certificate: {:?}", e);
+                return None;
+            }
+
+            ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth()
+        }
+        ClientTcpTls::PinnedFingerprint(fingerprint) => {
+            ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(FingerprintVerifier::new(Some(*fingerprint))))
+                .with_no_client_auth()
+        }
+        ClientTcpTls::AcceptAnyCertificate => {
+            ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(FingerprintVerifier::new(None)))
+                .with_no_client_auth()
+        }
+    };
+
+    Some(TlsConnector::from(Arc::new(client_config)))
+}
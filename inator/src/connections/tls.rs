@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use ring::digest::{digest, SHA256};
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{DigitallySignedStruct, Error as RustlsError, SignatureScheme};
+
+/// SHA-256 of the DER encoded certificate, the value clients pin with `with_pinned_fingerprint`.
+pub fn certificate_fingerprint(certificate: &CertificateDer<'_>) -> [u8; 32] {
+    let mut fingerprint = [0u8; 32];
+
+    fingerprint.copy_from_slice(digest(&SHA256, certificate.as_ref()).as_ref());
+
+    fingerprint
+}
+
+pub(crate) fn fingerprint_to_hex(fingerprint: &[u8; 32]) -> String {
+    fingerprint.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn self_signed_certificate(server_name: &str) -> Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    match rcgen::generate_simple_self_signed(vec![server_name.to_string()]) {
+        Ok(certified_key) => {
+            let key = PrivatePkcs8KeyDer::from(certified_key.signing_key.serialize_der());
+
+            Some((vec![certified_key.cert.der().clone()], key.into()))
+        }
+        Err(e) => {
+            eprintln!("Failed to generate self signed certificate: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Checks the server certificate against a pinned fingerprint, or accepts any certificate
+/// when there is nothing to pin, which is the case for a self signed certificate generated at startup.
+#[derive(Debug)]
+pub(crate) struct FingerprintVerifier {
+    pub(crate) fingerprint: Option<[u8; 32]>,
+    pub(crate) provider: Arc<CryptoProvider>
+}
+
+impl FingerprintVerifier {
+    pub(crate) fn new(fingerprint: Option<[u8; 32]>) -> Self {
+        FingerprintVerifier {
+            fingerprint,
+            provider: Arc::new(tokio_rustls::rustls::crypto::ring::default_provider())
+        }
+    }
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, RustlsError> {
+        match self.fingerprint {
+            Some(fingerprint) if certificate_fingerprint(end_entity) != fingerprint => {
+                Err(RustlsError::General("Server certificate does not match the pinned fingerprint".to_string()))
+            }
+            _ => Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, RustlsError> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, RustlsError> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verify(verifier: &FingerprintVerifier, certificate: &CertificateDer<'_>) -> Result<ServerCertVerified, RustlsError> {
+        let server_name = ServerName::try_from("localhost").unwrap();
+
+        verifier.verify_server_cert(certificate, &[], &server_name, &[], UnixTime::now())
+    }
+
+    #[test]
+    fn pinned_fingerprint_is_checked() {
+        let (certificates, _) = self_signed_certificate("localhost").unwrap();
+        let (other_certificates, _) = self_signed_certificate("localhost").unwrap();
+        let verifier = FingerprintVerifier::new(Some(certificate_fingerprint(&certificates[0])));
+
+        assert!(verify(&verifier, &certificates[0]).is_ok());
+        assert!(verify(&verifier, &other_certificates[0]).is_err());
+    }
+
+    #[test]
+    fn nothing_pinned_accepts_any_certificate() {
+        let (certificates, _) = self_signed_certificate("localhost").unwrap();
+
+        assert!(verify(&FingerprintVerifier::new(None), &certificates[0]).is_ok());
+    }
+}
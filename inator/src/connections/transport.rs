@@ -0,0 +1,90 @@
+use std::any::Any;
+use std::net::SocketAddr;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::runtime::Handle;
+use uuid::Uuid;
+use crate::connections::{ConnectionsType, MessageChannel};
+use crate::connections::compression::CompressionStats;
+use crate::systems::messaging::MessageTrait;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TransportState {
+    Stopped,
+    Connecting,
+    Connected
+}
+
+/// A server backend. `ServerPlugin` calls `update` and the `poll_*` methods once per frame,
+/// so implementations only move data between their own tasks and these calls.
+/// `send` returns false when the client is not connected through this transport.
+///
+/// Transports that work with bytes can use `encode_message` and `deserialize_message`
+/// to turn messages into frames and back.
+pub trait ServerTransport: Any + Send + Sync {
+    fn kind(&self) -> ConnectionsType;
+    /// The runtime of the App the transport belongs to, given before each start.
+    /// Transports without tasks ignore it.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_runtime(&mut self, _runtime: Handle) {}
+    fn start(&mut self);
+    fn state(&self) -> TransportState;
+    fn update(&mut self) {}
+    fn poll_accepted(&mut self) -> Vec<Uuid>;
+    fn poll_disconnected(&mut self) -> Vec<Uuid>;
+    fn poll_received(&mut self) -> Vec<(Uuid, Box<dyn MessageTrait>)>;
+    /// `channel` is the one registered for the message type, transports without channels ignore it.
+    fn send(&mut self, client: &Uuid, message: &dyn MessageTrait, channel: MessageChannel) -> bool;
+    fn disconnect_client(&mut self, client: &Uuid);
+    fn clients(&self) -> Vec<Uuid>;
+    /// Used to check the ban list, transports without addresses keep the default.
+    fn client_address(&self, _client: &Uuid) -> Option<SocketAddr> {
+        None
+    }
+    /// Clients that sent a frame the transport refused to read, the server closes their link.
+    /// Only length-prefixed transports reject frames.
+    fn poll_rejected(&mut self) -> Vec<Uuid> {
+        Vec::new()
+    }
+    /// Messages waiting to be written to this client, for transports with a send queue.
+    fn queue_depth(&self, _client: &Uuid) -> Option<usize> {
+        None
+    }
+    /// Whether messages queued during a frame are packed into batches, transports that pass
+    /// messages along without serializing them send each one as it is.
+    fn batches_messages(&self) -> bool {
+        true
+    }
+    /// Frame bytes before and after compression for this client, for transports that compress.
+    fn compression_stats(&self, _client: &Uuid) -> Option<CompressionStats> {
+        None
+    }
+    fn shutdown(&mut self);
+}
+
+/// A client backend, driven by `ClientPlugin` the same way `ServerTransport` is driven by `ServerPlugin`.
+pub trait ClientTransport: Any + Send + Sync {
+    fn kind(&self) -> ConnectionsType;
+    /// See `ServerTransport::set_runtime`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_runtime(&mut self, _runtime: Handle) {}
+    fn start(&mut self);
+    fn state(&self) -> TransportState;
+    fn update(&mut self) {}
+    fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>>;
+    fn send(&mut self, message: &dyn MessageTrait, channel: MessageChannel) -> bool;
+    /// Messages waiting to be written to the server, for transports with a send queue.
+    fn queue_depth(&self) -> Option<usize> {
+        None
+    }
+    /// Whether messages queued during a frame are packed into batches, see `ServerTransport`.
+    fn batches_messages(&self) -> bool {
+        true
+    }
+    /// Frame bytes before and after compression, for transports that compress.
+    fn compression_stats(&self) -> Option<CompressionStats> {
+        None
+    }
+    /// Drops the current link, `start` is called again on the next frame.
+    fn cancel(&mut self);
+    fn shutdown(&mut self);
+}
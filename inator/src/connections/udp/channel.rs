@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use crate::connections::MessageChannel;
+use crate::connections::udp::connection::UdpPacketKind;
+
+pub(crate) const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+pub(crate) const CHANNEL_HEADER_SIZE: usize = 4;
+/// Sequences a reliable channel keeps track of, packets further ahead are dropped unacknowledged.
+const RECEIVED_WINDOW: u16 = 1024;
+/// Resends of a reliable packet before the link is considered dead, about 3 seconds.
+const MAX_RESENDS: u32 = 30;
+
+struct PendingPacket {
+    packet: Vec<u8>,
+    last_sent: Instant,
+    resends: u32
+}
+
+#[derive(Default)]
+struct ChannelState {
+    next_sequence: u16,
+    pending: HashMap<u16, PendingPacket>,
+    next_expected: u16,
+    buffered: HashMap<u16, Vec<u8>>,
+    received: [u64; RECEIVED_WINDOW as usize / 64],
+    last_received: Option<u16>
+}
+
+#[derive(Default)]
+pub(crate) struct ChannelEndpoint {
+    channels: [ChannelState; 4],
+    dead: bool
+}
+
+pub(crate) struct ChannelIncoming {
+    pub(crate) delivered: Vec<Vec<u8>>,
+    pub(crate) ack: Option<Vec<u8>>
+}
+
+pub(crate) fn sequence_greater_than(a: u16, b: u16) -> bool {
+    ((a > b) && (a - b <= 32768)) || ((a < b) && (b - a > 32768))
+}
+
+fn header(kind: UdpPacketKind, channel: MessageChannel, sequence: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(CHANNEL_HEADER_SIZE);
+
+    packet.push(kind.to_byte());
+    packet.push(channel.to_byte());
+    packet.extend_from_slice(&sequence.to_le_bytes());
+
+    packet
+}
+
+pub(crate) fn read_header(packet: &[u8]) -> Option<(MessageChannel, u16, &[u8])> {
+    if packet.len() < CHANNEL_HEADER_SIZE {
+        return None;
+    }
+
+    let channel = MessageChannel::from_byte(packet[1])?;
+    let sequence = u16::from_le_bytes([packet[2], packet[3]]);
+
+    Some((channel, sequence, &packet[CHANNEL_HEADER_SIZE..]))
+}
+
+impl ChannelEndpoint {
+    fn state(&mut self, channel: MessageChannel) -> &mut ChannelState {
+        &mut self.channels[channel.to_byte() as usize]
+    }
+
+    pub(crate) fn outgoing(&mut self, channel: MessageChannel, payload: &[u8]) -> Vec<u8> {
+        let state = self.state(channel);
+        let sequence = state.next_sequence;
+
+        state.next_sequence = sequence.wrapping_add(1);
+
+        let mut packet = header(UdpPacketKind::Payload, channel, sequence);
+        packet.extend_from_slice(payload);
+
+        if channel.is_reliable() {
+            state.pending.insert(sequence, PendingPacket {
+                packet: packet.clone(),
+                last_sent: Instant::now(),
+                resends: 0
+            });
+        }
+
+        packet
+    }
+
+    /// Reliable packets are acknowledged unless they were dropped for being too far ahead,
+    /// duplicates included since the first ack may have been lost.
+    pub(crate) fn incoming(&mut self, channel: MessageChannel, sequence: u16, payload: &[u8]) -> ChannelIncoming {
+        let state = self.state(channel);
+        let mut delivered = Vec::new();
+        let mut acknowledged = channel.is_reliable();
+
+        match channel {
+            MessageChannel::ReliableOrdered => {
+                if sequence.wrapping_sub(state.next_expected) >= RECEIVED_WINDOW && sequence_greater_than(sequence, state.next_expected) {
+                    acknowledged = false;
+                } else if sequence == state.next_expected {
+                    delivered.push(payload.to_vec());
+                    state.next_expected = state.next_expected.wrapping_add(1);
+
+                    while let Some(buffered) = state.buffered.remove(&state.next_expected) {
+                        delivered.push(buffered);
+                        state.next_expected = state.next_expected.wrapping_add(1);
+                    }
+                } else if sequence_greater_than(sequence, state.next_expected) {
+                    state.buffered.entry(sequence).or_insert_with(|| payload.to_vec());
+                }
+            }
+            MessageChannel::ReliableUnordered => {
+                if state.receive_unordered(sequence) {
+                    delivered.push(payload.to_vec());
+                }
+            }
+            MessageChannel::Unreliable => {
+                delivered.push(payload.to_vec());
+            }
+            MessageChannel::UnreliableSequenced => {
+                let newer = match state.last_received {
+                    Some(last_received) => sequence_greater_than(sequence, last_received),
+                    None => true
+                };
+
+                if newer {
+                    state.last_received = Some(sequence);
+                    delivered.push(payload.to_vec());
+                }
+            }
+        }
+
+        ChannelIncoming {
+            delivered,
+            ack: acknowledged.then(|| header(UdpPacketKind::Ack, channel, sequence))
+        }
+    }
+
+    pub(crate) fn acknowledge(&mut self, channel: MessageChannel, sequence: u16) {
+        self.state(channel).pending.remove(&sequence);
+    }
+
+    /// Packets that were not acknowledged in time. A packet resent `MAX_RESENDS` times
+    /// marks the link as dead, see `is_dead`.
+    pub(crate) fn resends(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut resends = Vec::new();
+
+        for state in self.channels.iter_mut() {
+            for pending in state.pending.values_mut() {
+                if now.duration_since(pending.last_sent) < RESEND_INTERVAL {
+                    continue
+                }
+
+                if pending.resends >= MAX_RESENDS {
+                    self.dead = true;
+                    continue
+                }
+
+                pending.last_sent = now;
+                pending.resends += 1;
+                resends.push(pending.packet.clone());
+            }
+        }
+
+        resends
+    }
+
+    /// The peer stopped acknowledging reliable packets.
+    pub(crate) fn is_dead(&self) -> bool {
+        self.dead
+    }
+}
+
+impl ChannelState {
+    /// Marks the sequence as received, false for duplicates and sequences older than the window.
+    fn receive_unordered(&mut self, sequence: u16) -> bool {
+        let last_received = match self.last_received {
+            Some(last_received) => last_received,
+            None => {
+                self.last_received = Some(sequence);
+                self.mark_received(sequence);
+                return true;
+            }
+        };
+
+        if sequence_greater_than(sequence, last_received) {
+            let skipped = sequence.wrapping_sub(last_received).min(RECEIVED_WINDOW);
+
+            for offset in 1..=skipped {
+                self.clear_received(last_received.wrapping_add(offset));
+            }
+
+            self.last_received = Some(sequence);
+        } else if last_received.wrapping_sub(sequence) >= RECEIVED_WINDOW || self.was_received(sequence) {
+            return false;
+        }
+
+        self.mark_received(sequence);
+        true
+    }
+
+    fn was_received(&self, sequence: u16) -> bool {
+        let bit = sequence % RECEIVED_WINDOW;
+
+        self.received[bit as usize / 64] & (1 << (bit % 64)) != 0
+    }
+
+    fn mark_received(&mut self, sequence: u16) {
+        let bit = sequence % RECEIVED_WINDOW;
+
+        self.received[bit as usize / 64] |= 1 << (bit % 64);
+    }
+
+    fn clear_received(&mut self, sequence: u16) {
+        let bit = sequence % RECEIVED_WINDOW;
+
+        self.received[bit as usize / 64] &= !(1 << (bit % 64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive(endpoint: &mut ChannelEndpoint, packet: &[u8]) -> Vec<Vec<u8>> {
+        let (channel, sequence, payload) = read_header(packet).unwrap();
+
+        endpoint.incoming(channel, sequence, payload).delivered
+    }
+
+    #[test]
+    fn reliable_ordered_buffers_until_gap_is_filled() {
+        let mut sender = ChannelEndpoint::default();
+        let mut receiver = ChannelEndpoint::default();
+
+        let first = sender.outgoing(MessageChannel::ReliableOrdered, &[1]);
+        let second = sender.outgoing(MessageChannel::ReliableOrdered, &[2]);
+
+        assert!(receive(&mut receiver, &second).is_empty());
+        assert_eq!(receive(&mut receiver, &first), vec![vec![1], vec![2]]);
+        assert!(receive(&mut receiver, &first).is_empty());
+    }
+
+    #[test]
+    fn unreliable_sequenced_drops_stale_packets() {
+        let mut sender = ChannelEndpoint::default();
+        let mut receiver = ChannelEndpoint::default();
+
+        let first = sender.outgoing(MessageChannel::UnreliableSequenced, &[1]);
+        let second = sender.outgoing(MessageChannel::UnreliableSequenced, &[2]);
+
+        assert_eq!(receive(&mut receiver, &second), vec![vec![2]]);
+        assert!(receive(&mut receiver, &first).is_empty());
+    }
+
+    #[test]
+    fn acknowledged_packets_are_not_resent() {
+        let mut sender = ChannelEndpoint::default();
+
+        sender.outgoing(MessageChannel::ReliableUnordered, &[1]);
+        sender.outgoing(MessageChannel::Unreliable, &[2]);
+
+        let later = Instant::now() + RESEND_INTERVAL;
+        assert_eq!(sender.resends(later).len(), 1);
+
+        sender.acknowledge(MessageChannel::ReliableUnordered, 0);
+        assert!(sender.resends(later + RESEND_INTERVAL).is_empty());
+    }
+
+    #[test]
+    fn reliable_ordered_drops_packets_past_the_window() {
+        let mut receiver = ChannelEndpoint::default();
+
+        let incoming = receiver.incoming(MessageChannel::ReliableOrdered, RECEIVED_WINDOW, &[1]);
+
+        assert!(incoming.delivered.is_empty());
+        assert!(incoming.ack.is_none());
+        assert!(receiver.state(MessageChannel::ReliableOrdered).buffered.is_empty());
+
+        let incoming = receiver.incoming(MessageChannel::ReliableOrdered, RECEIVED_WINDOW - 1, &[2]);
+
+        assert!(incoming.ack.is_some());
+        assert_eq!(receiver.state(MessageChannel::ReliableOrdered).buffered.len(), 1);
+    }
+
+    #[test]
+    fn reliable_unordered_delivers_each_sequence_once() {
+        let mut receiver = ChannelEndpoint::default();
+        let mut delivered = 0;
+
+        for sequence in [0, 2, 1, 2, 0, 3000, 1, 2999, 3000] {
+            delivered += receiver.incoming(MessageChannel::ReliableUnordered, sequence, &[]).delivered.len();
+        }
+
+        assert_eq!(delivered, 5);
+        assert!(receiver.incoming(MessageChannel::ReliableUnordered, 3000 - RECEIVED_WINDOW, &[]).delivered.is_empty());
+    }
+
+    #[test]
+    fn unacknowledged_packets_end_the_link() {
+        let mut sender = ChannelEndpoint::default();
+
+        sender.outgoing(MessageChannel::ReliableOrdered, &[1]);
+
+        let mut now = Instant::now();
+
+        for _ in 0..MAX_RESENDS {
+            now += RESEND_INTERVAL;
+            assert_eq!(sender.resends(now).len(), 1);
+        }
+
+        assert!(!sender.is_dead());
+
+        now += RESEND_INTERVAL;
+        assert!(sender.resends(now).is_empty());
+        assert!(sender.is_dead());
+    }
+}
@@ -0,0 +1,336 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use crate::connections::{Connection, ConnectionsType, MessageChannel};
+use crate::connections::transport::{ClientTransport, TransportState};
+use crate::connections::udp::connection::{UdpConnection, UdpPacketKind, MAX_DATAGRAM_SIZE};
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+const CONNECT_HANDSHAKE_TRIES: u32 = 5;
+
+pub struct ClientUdpSettings {
+    pub(crate) address: IpAddr,
+    pub(crate) port: u16
+}
+
+pub struct ClientUdpConnection {
+    pub(crate) settings: ClientUdpSettings,
+    pub(crate) name: &'static str,
+    pub(crate) started: bool,
+    pub(crate) runtime: Option<Handle>,
+    pub(crate) dropped: Arc<AtomicBool>,
+    pub(crate) local_udp_connection: Option<UdpConnection>,
+    pub(crate) cancel_token: Arc<CancellationToken>,
+    pub(crate) connection_up_sender: Arc<UnboundedSender<(Arc<UdpSocket>, SocketAddr)>>,
+    pub(crate) connection_up_receiver: UnboundedReceiver<(Arc<UdpSocket>, SocketAddr)>
+}
+
+impl Default for ClientUdpSettings {
+    fn default() -> Self {
+        ClientUdpSettings {
+            address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            port: 8081
+        }
+    }
+}
+
+impl ClientUdpSettings {
+    pub fn new(address: IpAddr, port: u16) -> Self {
+        Self {
+            address,
+            port
+        }
+    }
+}
+
+impl ClientUdpConnection {
+    pub fn new(settings: ClientUdpSettings, name: &'static str) -> ClientUdpConnection {
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<(Arc<UdpSocket>, SocketAddr)>();
+
+        ClientUdpConnection {
+            settings,
+            name,
+            started: false,
+            runtime: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+            local_udp_connection: None,
+            cancel_token: Arc::new(CancellationToken::new()),
+            connection_up_sender: Arc::new(connection_up_sender),
+            connection_up_receiver
+        }
+    }
+}
+
+impl Connection for ClientUdpConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        let settings = &self.settings;
+        let server_addr = SocketAddr::new(settings.address, settings.port);
+        let local_addr = match settings.address {
+            IpAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            IpAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        };
+        let dropped = Arc::clone(&self.dropped);
+        let cancel_token = Arc::clone(&self.cancel_token);
+        let connection_up_sender = Arc::clone(&self.connection_up_sender);
+
+        self.started = true;
+        self.dropped.store(false, Ordering::SeqCst);
+
+        self.runtime.get_or_insert_with(network_runtime).spawn(async move {
+            let udp_socket = match UdpSocket::bind(local_addr).await {
+                Ok(socket) => socket,
+                Err(e) => {
+                    println!("Failed to bind udp socket: {}", e);
+                    dropped.store(true, Ordering::SeqCst);
+                    return;
+                }
+            };
+
+            if let Err(e) = udp_socket.connect(server_addr).await {
+                println!("Failed to connect to server: {}", e);
+                dropped.store(true, Ordering::SeqCst);
+                return;
+            }
+
+            // The connect packet or the answer can be lost, so one attempt sends it a few times.
+            let mut handshake_done = false;
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+
+            for _ in 0..CONNECT_HANDSHAKE_TRIES {
+                if cancel_token.is_cancelled() {
+                    return;
+                }
+
+                if let Err(e) = udp_socket.send(&[UdpPacketKind::Connect.to_byte()]).await {
+                    println!("Failed to connect to server: {}", e);
+                }
+
+                let accepted = tokio::time::timeout(CONNECT_RETRY_INTERVAL, async {
+                    loop {
+                        let size = udp_socket.recv(&mut buf).await?;
+
+                        if buf[..size].first().copied() == Some(UdpPacketKind::Accept.to_byte()) {
+                            return Ok::<(), std::io::Error>(());
+                        }
+                    }
+                }).await;
+
+                match accepted {
+                    Ok(Ok(())) => {
+                        handshake_done = true;
+                        break;
+                    }
+                    Ok(Err(e)) => {
+                        println!("Failed to connect to server: {}", e);
+
+                        tokio::time::sleep(CONNECT_RETRY_INTERVAL).await;
+                    }
+                    Err(_) => continue,
+                }
+            }
+
+            if !handshake_done {
+                println!("Failed to connect to server: no answer from {}", server_addr);
+                dropped.store(true, Ordering::SeqCst);
+                return;
+            }
+
+            if cancel_token.is_cancelled() {
+                return;
+            }
+
+            let _ = connection_up_sender.send((Arc::new(udp_socket), server_addr));
+        });
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(local_udp_connection) = self.local_udp_connection.take() {
+            drop(local_udp_connection);
+        }
+
+        self.cancel_token.cancel();
+        self.cancel_token = Arc::new(CancellationToken::new());
+        self.dropped.store(true, Ordering::SeqCst);
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(mut local_udp_connection) = self.local_udp_connection.take() {
+            local_udp_connection.send_disconnect();
+        }
+
+        self.runtime.take();
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ClientTransport for ClientUdpConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Udp
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.local_udp_connection.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok((udp_socket, socket_addr)) = self.connection_up_receiver.try_recv() {
+            if let Some(local_udp_connection) = self.local_udp_connection.take() {
+                drop(local_udp_connection);
+            }
+
+            let mut udp_connection = UdpConnection::new(udp_socket, socket_addr, self.name, NetworkSide::Client, Arc::clone(&self.cancel_token));
+
+            udp_connection.start_listening(self.runtime.as_ref().unwrap());
+
+            self.local_udp_connection = Some(udp_connection);
+        } else if self.started && self.local_udp_connection.is_none() && self.dropped.load(Ordering::SeqCst) {
+            self.started = false;
+        }
+
+        let connection_down = match self.local_udp_connection.as_mut() {
+            Some(local_udp_connection) => local_udp_connection.connection_down_receiver.try_recv().is_ok(),
+            None => false
+        };
+
+        if connection_down {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>> {
+        let mut received = Vec::new();
+
+        if let Some(local_udp_connection) = self.local_udp_connection.as_mut() {
+            while let Ok(message) = local_udp_connection.message_received_receiver.try_recv() {
+                received.push(message);
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, message: &dyn MessageTrait, channel: MessageChannel) -> bool {
+        match self.local_udp_connection.as_mut() {
+            Some(local_udp_connection) => {
+                local_udp_connection.send_message(message, channel);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn queue_depth(&self) -> Option<usize> {
+        self.local_udp_connection.as_ref().map(|local_udp_connection| local_udp_connection.queue_depth())
+    }
+
+    fn cancel(&mut self) {
+        self.cancel_connection()
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use super::*;
+    use uuid::Uuid;
+    use crate::connections::heartbeat::HeartbeatPing;
+    use crate::connections::transport::ServerTransport;
+    use crate::connections::udp::server::{ServerUdpConnection, ServerUdpSettings};
+
+    fn connected_pair() -> (ServerUdpConnection, ClientUdpConnection, Vec<Uuid>) {
+        let port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let address = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut server = ServerUdpConnection::new(ServerUdpSettings::new(address, port, 0), "udp");
+        let mut client = ClientUdpConnection::new(ClientUdpSettings::new(address, port), "udp");
+        let mut accepted = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        server.start();
+        client.start();
+
+        while client.state() != TransportState::Connected {
+            assert!(Instant::now() < deadline, "timed out");
+
+            server.update();
+            client.update();
+            accepted.extend(server.poll_accepted());
+
+            if client.state() == TransportState::Stopped {
+                client.start();
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        accepted.extend(server.poll_accepted());
+
+        (server, client, accepted)
+    }
+
+    #[test]
+    fn connects_without_traffic_from_the_server() {
+        let (_server, _client, accepted) = connected_pair();
+
+        assert_eq!(accepted.len(), 1);
+    }
+
+    #[test]
+    fn sequenced_messages_leave_in_order() {
+        let (mut server, mut client, _) = connected_pair();
+        let mut received = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        for sent_at in 0..50 {
+            assert!(client.send(&HeartbeatPing { sent_at }, MessageChannel::UnreliableSequenced));
+        }
+
+        while received.len() < 50 {
+            assert!(Instant::now() < deadline, "received {:?}", received);
+
+            for (_, message) in server.poll_received() {
+                received.push(message.as_any().downcast_ref::<HeartbeatPing>().unwrap().sent_at);
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(received, (0..50).collect::<Vec<_>>());
+        assert_eq!(client.queue_depth(), Some(0));
+    }
+}
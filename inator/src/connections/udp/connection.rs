@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::net::UdpSocket;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use crate::connections::udp::channel::{read_header, ChannelEndpoint, CHANNEL_HEADER_SIZE, RESEND_INTERVAL};
+use crate::NetworkSide;
+use crate::connections::MessageChannel;
+use crate::systems::messaging::{deserialize_message, encode_message, MessageTrait};
+
+pub(crate) const MAX_DATAGRAM_SIZE: usize = 65507;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub(crate) enum UdpPacketKind {
+    Connect,
+    Payload,
+    Disconnect,
+    Ack,
+    /// The server's answer to `Connect`, sent again for every `Connect` in case it was lost.
+    Accept
+}
+
+pub(crate) struct UdpRoute {
+    pub(crate) message_received_sender: Arc<UnboundedSender<Box<dyn MessageTrait>>>,
+    pub(crate) connection_down_sender: Arc<UnboundedSender<()>>,
+    pub(crate) channels: Arc<StdMutex<ChannelEndpoint>>,
+}
+
+pub(crate) type UdpRoutes = Arc<StdMutex<HashMap<SocketAddr, UdpRoute>>>;
+
+pub struct UdpConnection {
+    pub socket: Option<Arc<UdpSocket>>,
+    pub connection_name: &'static str,
+    pub socket_addr: SocketAddr,
+    pub network_side: NetworkSide,
+    pub uuid: Option<Uuid>,
+    pub cancellation_token: Arc<CancellationToken>,
+    pub connection_down_sender: Arc<UnboundedSender<()>>,
+    pub connection_down_receiver: UnboundedReceiver<()>,
+    pub message_received_sender: Arc<UnboundedSender<Box<dyn MessageTrait>>>,
+    pub message_received_receiver: UnboundedReceiver<Box<dyn MessageTrait>>,
+    pub listening: bool,
+    pub(crate) channels: Arc<StdMutex<ChannelEndpoint>>,
+    pub(crate) routes: Option<UdpRoutes>,
+    pub(crate) outgoing_sender: Option<UnboundedSender<Vec<u8>>>,
+    pub(crate) outgoing_receiver: Option<UnboundedReceiver<Vec<u8>>>,
+    pub(crate) queued: Arc<AtomicUsize>
+}
+
+impl UdpPacketKind {
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            UdpPacketKind::Connect => 0,
+            UdpPacketKind::Payload => 1,
+            UdpPacketKind::Disconnect => 2,
+            UdpPacketKind::Ack => 3,
+            UdpPacketKind::Accept => 4,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<UdpPacketKind> {
+        match byte {
+            0 => Some(UdpPacketKind::Connect),
+            1 => Some(UdpPacketKind::Payload),
+            2 => Some(UdpPacketKind::Disconnect),
+            3 => Some(UdpPacketKind::Ack),
+            4 => Some(UdpPacketKind::Accept),
+            _ => None
+        }
+    }
+}
+
+pub(crate) fn route_packet(packet: &[u8], route: &UdpRoute) -> Option<Vec<u8>> {
+    let kind = packet.first().copied().and_then(UdpPacketKind::from_byte);
+
+    match kind {
+        Some(UdpPacketKind::Payload) => {
+            let (channel, sequence, payload) = match read_header(packet) {
+                Some(header) => header,
+                None => {
+                    eprintln!("Invalid udp packet received");
+                    return None;
+                }
+            };
+
+            let incoming = route.channels.lock().unwrap().incoming(channel, sequence, payload);
+
+            for payload in incoming.delivered {
+                if let Some(message) = deserialize_message(&payload) {
+                    let _ = route.message_received_sender.send(message);
+                } else {
+                    eprintln!("Message not registered or failed to deserialize");
+                }
+            }
+
+            incoming.ack
+        }
+        Some(UdpPacketKind::Ack) => {
+            if let Some((channel, sequence, _)) = read_header(packet) {
+                route.channels.lock().unwrap().acknowledge(channel, sequence);
+            }
+
+            None
+        }
+        Some(UdpPacketKind::Disconnect) => {
+            let _ = route.connection_down_sender.send(());
+
+            None
+        }
+        Some(UdpPacketKind::Connect) => Some(vec![UdpPacketKind::Accept.to_byte()]),
+        Some(UdpPacketKind::Accept) => None,
+        None => {
+            eprintln!("Invalid udp packet received");
+
+            None
+        }
+    }
+}
+
+pub(crate) fn collect_resends(routes: &UdpRoutes) -> Vec<(SocketAddr, Vec<u8>)> {
+    let now = Instant::now();
+    let mut resends = Vec::new();
+
+    for (socket_addr, route) in routes.lock().unwrap().iter() {
+        let mut channels = route.channels.lock().unwrap();
+
+        for packet in channels.resends(now) {
+            resends.push((*socket_addr, packet));
+        }
+
+        if channels.is_dead() {
+            let _ = route.connection_down_sender.send(());
+        }
+    }
+
+    resends
+}
+
+impl Drop for UdpConnection {
+    fn drop(&mut self) {
+        if let Some(routes) = self.routes.take() {
+            routes.lock().unwrap().remove(&self.socket_addr);
+        }
+
+        drop(self.socket.take());
+    }
+}
+
+impl UdpConnection {
+    pub fn new(socket: Arc<UdpSocket>, socket_addr: SocketAddr, connection_name: &'static str, network_side: NetworkSide, cancellation_token: Arc<CancellationToken>) -> Self {
+        let (connection_down_sender, connection_down_receiver) = unbounded_channel::<()>();
+        let (message_received_sender, message_received_receiver) = unbounded_channel::<Box<dyn MessageTrait>>();
+        let (outgoing_sender, outgoing_receiver) = unbounded_channel::<Vec<u8>>();
+
+        UdpConnection {
+            socket: Some(socket),
+            connection_name,
+            socket_addr,
+            network_side,
+            uuid: if network_side == NetworkSide::Server {Some(Uuid::new_v4())} else {None},
+            cancellation_token: Arc::clone(&cancellation_token),
+            connection_down_sender: Arc::new(connection_down_sender),
+            connection_down_receiver,
+            message_received_sender: Arc::new(message_received_sender),
+            message_received_receiver,
+            listening: false,
+            channels: Arc::new(StdMutex::new(ChannelEndpoint::default())),
+            routes: None,
+            outgoing_sender: Some(outgoing_sender),
+            outgoing_receiver: Some(outgoing_receiver),
+            queued: Arc::new(AtomicUsize::new(0))
+        }
+    }
+
+    pub(crate) fn route(&self) -> UdpRoute {
+        UdpRoute {
+            message_received_sender: Arc::clone(&self.message_received_sender),
+            connection_down_sender: Arc::clone(&self.connection_down_sender),
+            channels: Arc::clone(&self.channels),
+        }
+    }
+
+    pub(crate) fn register_route(&mut self, routes: &UdpRoutes) {
+        routes.lock().unwrap().insert(self.socket_addr, self.route());
+
+        self.routes = Some(Arc::clone(routes));
+    }
+
+    pub fn start_listening(&mut self, runtime: &Handle) {
+        self.start_writing(runtime);
+
+        let socket = match &self.socket {
+            Some(socket) => Arc::clone(socket),
+            None => return,
+        };
+
+        let route = self.route();
+        let channels = Arc::clone(&self.channels);
+        let cancellation_token = Arc::clone(&self.cancellation_token);
+        let network_side = self.network_side;
+
+        self.listening = true;
+
+        runtime.spawn(async move {
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+            let mut resend_interval = tokio::time::interval(RESEND_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+
+                    _ = resend_interval.tick() => {
+                        let (resends, dead) = {
+                            let mut channels = channels.lock().unwrap();
+
+                            (channels.resends(Instant::now()), channels.is_dead())
+                        };
+
+                        if dead {
+                            println!("Udp peer stopped acknowledging packets");
+
+                            let _ = route.connection_down_sender.send(());
+
+                            break;
+                        }
+
+                        for packet in resends {
+                            if let Err(e) = socket.send(&packet).await {
+                                eprintln!("Failed to resend datagram: {:?}", e);
+                            }
+                        }
+                    }
+
+                    result = socket.recv(&mut buf) => {
+                        match result {
+                            Ok(size) => {
+                                if let Some(ack) = route_packet(&buf[..size], &route)
+                                    && let Err(e) = socket.send(&ack).await {
+                                    eprintln!("Failed to send ack: {:?}", e);
+                                }
+                            },
+                            Err(e) => {
+                                eprintln!("Failed to receive datagram: {:?}", e);
+                                eprintln!("From {:?}", network_side);
+
+                                match e.kind() {
+                                    std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::ConnectionReset => {
+                                        println!("Udp peer is unreachable");
+
+                                        let _ = route.connection_down_sender.send(());
+
+                                        break;
+                                    },
+                                    _ => {
+                                        println!("Unexpected error: {:?}", e.kind());
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Sends the queued packets one at a time, so they leave the socket in the order they were sequenced.
+    pub(crate) fn start_writing(&mut self, runtime: &Handle) {
+        let (Some(socket), Some(mut outgoing_receiver)) = (self.socket.as_ref().map(Arc::clone), self.outgoing_receiver.take()) else {
+            return;
+        };
+
+        let socket_addr = self.socket_addr;
+        let network_side = self.network_side;
+        let cancellation_token = Arc::clone(&self.cancellation_token);
+        let queued = Arc::clone(&self.queued);
+
+        runtime.spawn(async move {
+            loop {
+                let packet = tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+                    packet = outgoing_receiver.recv() => match packet {
+                        Some(packet) => packet,
+                        None => break
+                    }
+                };
+
+                queued.fetch_sub(1, Ordering::Relaxed);
+
+                let result = if network_side == NetworkSide::Server {
+                    socket.send_to(&packet, socket_addr).await
+                } else {
+                    socket.send(&packet).await
+                };
+
+                if let Err(e) = result {
+                    eprintln!("Failed to send datagram: {:?}", e);
+                    eprintln!("From {:?}", network_side);
+                }
+            }
+        });
+    }
+
+    pub fn send_message(&mut self, message: &dyn MessageTrait, channel: MessageChannel) {
+        let Some(outgoing_sender) = &self.outgoing_sender else {
+            return;
+        };
+
+        let payload = match encode_message(message) {
+            Some(payload) => payload,
+            None => return,
+        };
+
+        if payload.len() + CHANNEL_HEADER_SIZE > MAX_DATAGRAM_SIZE {
+            eprintln!("Message of {} bytes does not fit in a udp datagram", payload.len());
+            return;
+        }
+
+        let packet = self.channels.lock().unwrap().outgoing(channel, &payload);
+
+        self.queued.fetch_add(1, Ordering::Relaxed);
+
+        if outgoing_sender.send(packet).is_err() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            eprintln!("Udp writer is closed");
+        }
+    }
+
+    /// Packets queued by `send_message` that the writer task has not taken yet.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn send_disconnect(&mut self) {
+        let socket = match &self.socket {
+            Some(socket) => socket,
+            None => return,
+        };
+
+        let packet = [UdpPacketKind::Disconnect.to_byte()];
+        let result = if self.network_side == NetworkSide::Server {
+            socket.try_send_to(&packet, self.socket_addr)
+        } else {
+            socket.try_send(&packet)
+        };
+
+        if let Err(e) = result {
+            eprintln!("Failed to send disconnect: {:?}", e);
+        }
+    }
+}
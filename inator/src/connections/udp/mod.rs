@@ -0,0 +1,4 @@
+﻿pub mod server;
+pub mod client;
+pub mod connection;
+pub(crate) mod channel;
\ No newline at end of file
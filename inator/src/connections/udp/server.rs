@@ -0,0 +1,425 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::net::UdpSocket;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use crate::connections::{BIND_RETRY_INTERVAL, Connection, ConnectionsType, MessageChannel};
+use crate::connections::udp::channel::RESEND_INTERVAL;
+use crate::connections::transport::{ServerTransport, TransportState};
+use crate::connections::udp::connection::{collect_resends, route_packet, UdpConnection, UdpPacketKind, UdpRoutes, MAX_DATAGRAM_SIZE};
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+/// Connects a server holds on to until the next `poll_accepted`, others are ignored until then.
+const MAX_PENDING_ACCEPTS: usize = 1024;
+
+pub struct ServerUdpSettings {
+    pub(crate) address: IpAddr,
+    pub(crate) port: u16,
+    pub(crate) max_connections: usize
+}
+
+pub struct ServerUdpConnection {
+    pub(crate) settings: ServerUdpSettings,
+    pub(crate) name: &'static str,
+    pub(crate) socket: Option<Arc<UdpSocket>>,
+    pub(crate) started: bool,
+    pub(crate) runtime: Option<Handle>,
+    pub(crate) dropped: Arc<AtomicBool>,
+    pub(crate) cancel_token: Arc<CancellationToken>,
+    pub(crate) routes: UdpRoutes,
+    /// Addresses that were sent an `Accept` and are not routed yet, they count toward `max_connections`.
+    pub(crate) pending_accepts: Arc<StdMutex<HashSet<SocketAddr>>>,
+    pub(crate) connection_down_sender: Arc<UnboundedSender<()>>,
+    pub(crate) connection_down_receiver: UnboundedReceiver<()>,
+    pub(crate) connection_up_sender: Arc<UnboundedSender<Arc<UdpSocket>>>,
+    pub(crate) connection_up_receiver: UnboundedReceiver<Arc<UdpSocket>>,
+    pub(crate) client_connected_sender: Arc<UnboundedSender<SocketAddr>>,
+    pub(crate) client_connected_receiver: UnboundedReceiver<SocketAddr>,
+    pub(crate) connections: HashMap<Uuid, UdpConnection>
+}
+
+impl Default for ServerUdpSettings {
+    fn default() -> Self {
+        ServerUdpSettings {
+            address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            port: 8081,
+            max_connections: 0
+        }
+    }
+}
+
+impl ServerUdpSettings {
+    pub fn new(address: IpAddr, port: u16, max_connections: usize) -> Self {
+        Self {
+            address,
+            port,
+            max_connections
+        }
+    }
+}
+
+impl ServerUdpConnection {
+    pub fn new(settings: ServerUdpSettings, name: &'static str) -> ServerUdpConnection {
+        let (connection_down_sender, connection_down_receiver) = unbounded_channel::<()>();
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<Arc<UdpSocket>>();
+        let (client_connected_sender, client_connected_receiver) = unbounded_channel::<SocketAddr>();
+
+        ServerUdpConnection {
+            settings,
+            name,
+            socket: None,
+            started: false,
+            runtime: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+            cancel_token: Arc::new(CancellationToken::new()),
+            routes: Arc::new(StdMutex::new(HashMap::new())),
+            pending_accepts: Arc::new(StdMutex::new(HashSet::new())),
+            connection_down_sender: Arc::new(connection_down_sender),
+            connection_down_receiver,
+            connection_up_sender: Arc::new(connection_up_sender),
+            connection_up_receiver,
+            client_connected_sender: Arc::new(client_connected_sender),
+            client_connected_receiver,
+            connections: HashMap::new()
+        }
+    }
+
+    pub(crate) fn is_routed(&self, socket_addr: &SocketAddr) -> bool {
+        self.routes.lock().unwrap().contains_key(socket_addr)
+    }
+}
+
+impl Connection for ServerUdpConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        let settings = &self.settings;
+        let max_connections = settings.max_connections;
+        let address = (settings.address, settings.port);
+        let dropped = Arc::clone(&self.dropped);
+        let routes = Arc::clone(&self.routes);
+        let pending_accepts = Arc::clone(&self.pending_accepts);
+        let connection_up_sender = Arc::clone(&self.connection_up_sender);
+        let connection_down_sender = Arc::clone(&self.connection_down_sender);
+        let client_connected_sender = Arc::clone(&self.client_connected_sender);
+        let cancel_token = Arc::clone(&self.cancel_token);
+
+        self.started = true;
+
+        self.runtime.get_or_insert_with(network_runtime).spawn(async move {
+            dropped.store(false, Ordering::SeqCst);
+
+            let udp_socket = loop {
+                match UdpSocket::bind(address).await {
+                    Ok(socket) => break Arc::new(socket),
+                    Err(e) => {
+                        println!("Error on bind: {}, trying again...", e);
+
+                        if dropped.load(Ordering::SeqCst) {
+                            return;
+                        }
+
+                        tokio::time::sleep(BIND_RETRY_INTERVAL).await;
+                    }
+                };
+            };
+
+            if dropped.load(Ordering::SeqCst) {
+                drop(udp_socket);
+                return;
+            }
+
+            println!("Server udp binded successfully!");
+
+            let _ = connection_up_sender.send(Arc::clone(&udp_socket));
+
+            let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+            let mut resend_interval = tokio::time::interval(RESEND_INTERVAL);
+
+            loop {
+                tokio::select! {
+                    _ = cancel_token.cancelled() => {
+                        break;
+                    },
+                    _ = resend_interval.tick() => {
+                        for (addr, packet) in collect_resends(&routes) {
+                            if let Err(e) = udp_socket.send_to(&packet, addr).await {
+                                eprintln!("Failed to resend datagram: {:?}", e);
+                            }
+                        }
+                    },
+                    recv_result = udp_socket.recv_from(&mut buf) => {
+                        match recv_result {
+                            Ok((size, addr)) => {
+                                let packet = &buf[..size];
+                                let ack = {
+                                    let routes = routes.lock().unwrap();
+
+                                    if let Some(route) = routes.get(&addr) {
+                                        route_packet(packet, route)
+                                    } else {
+                                        if packet.first().copied() == Some(UdpPacketKind::Connect.to_byte()) {
+                                            let mut pending_accepts = pending_accepts.lock().unwrap();
+
+                                            if pending_accepts.contains(&addr) {
+                                                // The Accept was lost, the client is already queued.
+                                                Some(vec![UdpPacketKind::Accept.to_byte()])
+                                            } else if max_connections > 0 && routes.len() + pending_accepts.len() >= max_connections {
+                                                println!("Connection is full, rejecting connection to {}", addr);
+
+                                                None
+                                            } else if pending_accepts.len() >= MAX_PENDING_ACCEPTS {
+                                                None
+                                            } else {
+                                                println!("Accepted connection from {}", addr);
+
+                                                pending_accepts.insert(addr);
+
+                                                let _ = client_connected_sender.send(addr);
+
+                                                Some(vec![UdpPacketKind::Accept.to_byte()])
+                                            }
+                                        } else {
+                                            None
+                                        }
+                                    }
+                                };
+
+                                if let Some(ack) = ack
+                                    && let Err(e) = udp_socket.send_to(&ack, addr).await {
+                                    eprintln!("Failed to send ack: {:?}", e);
+                                }
+                            },
+                            Err(e) => {
+                                eprintln!("Error on receive: {:?}", e);
+
+                                match e.kind() {
+                                    std::io::ErrorKind::ConnectionAborted => {
+                                        println!("Socket aborted (network down or aborted by OS)");
+
+                                        let _ = connection_down_sender.send(());
+
+                                        break;
+                                    },
+                                    std::io::ErrorKind::Other => {
+                                        println!("Socket was probably closed manually");
+
+                                        let _ = connection_down_sender.send(());
+
+                                        break;
+                                    },
+                                    _ => {
+                                        println!("Unexpected error: {:?}", e.kind());
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if dropped.load(Ordering::SeqCst) {
+                    drop(udp_socket);
+                    break;
+                }
+            }
+        });
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(socket) = self.socket.take() {
+            drop(socket);
+        }
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        for client_connection in self.connections.values_mut() {
+            client_connection.send_disconnect();
+        }
+
+        self.runtime.take();
+
+        if let Some(socket) = self.socket.take() {
+            drop(socket);
+        }
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ServerTransport for ServerUdpConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Udp
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.socket.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok(udp_socket) = self.connection_up_receiver.try_recv() {
+            self.socket = Some(udp_socket);
+        }
+
+        if self.connection_down_receiver.try_recv().is_ok() {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_accepted(&mut self) -> Vec<Uuid> {
+        let mut accepted = Vec::new();
+
+        while let Ok(socket_addr) = self.client_connected_receiver.try_recv() {
+            self.pending_accepts.lock().unwrap().remove(&socket_addr);
+
+            if self.is_routed(&socket_addr) {continue}
+
+            let socket = match self.socket.as_ref() {
+                Some(socket) => Arc::clone(socket),
+                None => continue,
+            };
+
+            let max_connections = self.settings.max_connections;
+
+            if max_connections > 0 && self.connections.len() >= max_connections {
+                println!("Connection is full, dropping accepted connection from {}", socket_addr);
+
+                let _ = socket.try_send_to(&[UdpPacketKind::Disconnect.to_byte()], socket_addr);
+
+                continue
+            }
+
+            let mut udp_connection = UdpConnection::new(socket, socket_addr, self.name, NetworkSide::Server, Arc::clone(&self.cancel_token));
+            let current_uuid = udp_connection.uuid.unwrap();
+
+            udp_connection.register_route(&self.routes);
+            udp_connection.start_writing(self.runtime.as_ref().unwrap());
+
+            self.connections.insert(current_uuid,udp_connection);
+            accepted.push(current_uuid);
+        }
+
+        accepted
+    }
+
+    fn poll_disconnected(&mut self) -> Vec<Uuid> {
+        let mut disconnected = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            if client_connection.connection_down_receiver.try_recv().is_ok() {
+                disconnected.push(*uuid);
+            }
+        }
+
+        for uuid in disconnected.iter() {
+            self.connections.remove(uuid);
+        }
+
+        disconnected
+    }
+
+    fn poll_received(&mut self) -> Vec<(Uuid, Box<dyn MessageTrait>)> {
+        let mut received = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            while let Ok(message) = client_connection.message_received_receiver.try_recv() {
+                received.push((*uuid, message));
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, client: &Uuid, message: &dyn MessageTrait, channel: MessageChannel) -> bool {
+        match self.connections.get_mut(client) {
+            Some(client_connection) => {
+                client_connection.send_message(message, channel);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn queue_depth(&self, client: &Uuid) -> Option<usize> {
+        self.connections.get(client).map(|client_connection| client_connection.queue_depth())
+    }
+
+    fn disconnect_client(&mut self, client: &Uuid) {
+        if let Some(mut client_connection) = self.connections.remove(client) {
+            client_connection.send_disconnect();
+        }
+    }
+
+    fn clients(&self) -> Vec<Uuid> {
+        self.connections.keys().copied().collect()
+    }
+
+    fn client_address(&self, client: &Uuid) -> Option<SocketAddr> {
+        self.connections.get(client).map(|client_connection| client_connection.socket_addr)
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    #[test]
+    fn connects_in_the_same_frame_respect_max_connections() {
+        let port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut server = ServerUdpConnection::new(ServerUdpSettings::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port, 1), "udp");
+        let clients: Vec<_> = (0..3).map(|_| std::net::UdpSocket::bind("127.0.0.1:0").unwrap()).collect();
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        server.start();
+
+        while server.socket.is_none() {
+            assert!(Instant::now() < deadline, "timed out");
+
+            server.update();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        for client in &clients {
+            client.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+            client.send_to(&[UdpPacketKind::Connect.to_byte()], ("127.0.0.1", port)).unwrap();
+        }
+
+        let answered = clients.iter().filter(|client| client.recv(&mut [0u8; 16]).is_ok()).count();
+
+        assert_eq!(answered, 1);
+        assert_eq!(server.poll_accepted().len(), 1);
+    }
+}
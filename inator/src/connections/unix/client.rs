@@ -0,0 +1,275 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::net::UnixStream;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use crate::connections::{BytesOptions, Connection, ConnectionsType, MessageChannel, OrderOptions};
+use crate::connections::tcp::connection::{TcpConnection, TcpTransportStream, DEFAULT_MAX_FRAME_SIZE};
+use crate::connections::transport::{ClientTransport, TransportState};
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+pub struct ClientUnixSettings {
+    pub(crate) path: PathBuf,
+    pub(crate) bytes: BytesOptions,
+    pub(crate) order: OrderOptions,
+    pub(crate) max_frame_size: usize
+}
+
+pub struct ClientUnixConnection {
+    pub(crate) settings: ClientUnixSettings,
+    pub(crate) name: &'static str,
+    pub(crate) started: bool,
+    pub(crate) runtime: Option<Handle>,
+    pub(crate) dropped: Arc<AtomicBool>,
+    pub(crate) local_unix_connection: Option<TcpConnection>,
+    pub(crate) cancel_token: Arc<CancellationToken>,
+    pub(crate) connection_up_sender: Arc<UnboundedSender<UnixStream>>,
+    pub(crate) connection_up_receiver: UnboundedReceiver<UnixStream>
+}
+
+impl Default for ClientUnixSettings {
+    fn default() -> Self {
+        ClientUnixSettings {
+            path: std::env::temp_dir().join("inator.sock"),
+            bytes: BytesOptions::U32,
+            order: OrderOptions::LittleEndian,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE
+        }
+    }
+}
+
+impl ClientUnixSettings {
+    pub fn new(path: impl Into<PathBuf>, bytes: BytesOptions, order: OrderOptions) -> Self {
+        Self {
+            path: path.into(),
+            bytes,
+            order,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE
+        }
+    }
+
+    /// Frames announcing more bytes than this are rejected and the peer is disconnected,
+    /// by default `DEFAULT_MAX_FRAME_SIZE`.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+}
+
+impl ClientUnixConnection {
+    pub fn new(settings: ClientUnixSettings, name: &'static str) -> ClientUnixConnection {
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<UnixStream>();
+
+        ClientUnixConnection {
+            settings,
+            name,
+            started: false,
+            runtime: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+            local_unix_connection: None,
+            cancel_token: Arc::new(CancellationToken::new()),
+            connection_up_sender: Arc::new(connection_up_sender),
+            connection_up_receiver
+        }
+    }
+}
+
+impl Connection for ClientUnixConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        let path = self.settings.path.clone();
+        let dropped = Arc::clone(&self.dropped);
+        let cancel_token = Arc::clone(&self.cancel_token);
+        let connection_up_sender = Arc::clone(&self.connection_up_sender);
+
+        self.started = true;
+        self.dropped.store(false, Ordering::SeqCst);
+
+        self.runtime.get_or_insert_with(network_runtime).spawn(async move {
+            let unix_stream = match UnixStream::connect(&path).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("Failed to connect to server: {}", e);
+                    dropped.store(true, Ordering::SeqCst);
+                    return;
+                }
+            };
+
+            if cancel_token.is_cancelled() {
+                return;
+            }
+
+            connection_up_sender.send(unix_stream).unwrap();
+        });
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(local_unix_connection) = self.local_unix_connection.take() {
+            drop(local_unix_connection);
+        }
+
+        self.cancel_token.cancel();
+        self.cancel_token = Arc::new(CancellationToken::new());
+        self.dropped.store(true, Ordering::SeqCst);
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        self.runtime.take();
+
+        if let Some(local_unix_connection) = self.local_unix_connection.take() {
+            drop(local_unix_connection);
+        }
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ClientTransport for ClientUnixConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Unix
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.local_unix_connection.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok(unix_stream) = self.connection_up_receiver.try_recv() {
+            if let Some(local_unix_connection) = self.local_unix_connection.take() {
+                drop(local_unix_connection);
+            }
+
+            let settings = &self.settings;
+            let mut unix_connection = TcpConnection::new(TcpTransportStream::Unix(unix_stream), self.name, NetworkSide::Client, Arc::clone(&self.cancel_token),settings.bytes,settings.order,settings.max_frame_size);
+
+            unix_connection.start_listening(self.runtime.as_ref().unwrap());
+
+            self.local_unix_connection = Some(unix_connection);
+        } else if self.started && self.local_unix_connection.is_none() && self.dropped.load(Ordering::SeqCst) {
+            self.started = false;
+        }
+
+        let connection_down = match self.local_unix_connection.as_mut() {
+            Some(local_unix_connection) => local_unix_connection.connection_down_receiver.try_recv().is_ok(),
+            None => false
+        };
+
+        if connection_down {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>> {
+        let mut received = Vec::new();
+
+        if let Some(local_unix_connection) = self.local_unix_connection.as_mut() {
+            while let Ok(message) = local_unix_connection.message_received_receiver.try_recv() {
+                received.push(message);
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+        match self.local_unix_connection.as_mut() {
+            Some(local_unix_connection) => {
+                local_unix_connection.send_message(message);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn queue_depth(&self) -> Option<usize> {
+        self.local_unix_connection.as_ref().map(|local_unix_connection| local_unix_connection.queue_depth())
+    }
+
+    fn cancel(&mut self) {
+        self.cancel_connection()
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::*;
+    use crate::connections::heartbeat::HeartbeatPing;
+    use crate::connections::transport::ServerTransport;
+    use crate::connections::unix::server::{ServerUnixConnection, ServerUnixSettings};
+
+    #[test]
+    fn message_reaches_the_server_over_the_socket() {
+        let path = std::env::temp_dir().join(format!("inator-test-{}.sock", std::process::id()));
+        let mut server = ServerUnixConnection::new(ServerUnixSettings::new(&path, BytesOptions::U32, OrderOptions::LittleEndian, 0), "unix");
+        let mut client = ClientUnixConnection::new(ClientUnixSettings::new(&path, BytesOptions::U32, OrderOptions::LittleEndian), "unix");
+        let mut accepted = Vec::new();
+        let mut received = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        server.start();
+        client.start();
+
+        while client.state() != TransportState::Connected {
+            assert!(Instant::now() < deadline, "timed out connecting");
+
+            server.update();
+            client.update();
+            accepted.extend(server.poll_accepted());
+
+            if client.state() == TransportState::Stopped {
+                client.start();
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(client.send(&HeartbeatPing { sent_at: 42 }, MessageChannel::ReliableOrdered));
+
+        while received.is_empty() {
+            assert!(Instant::now() < deadline, "timed out receiving");
+
+            server.update();
+            accepted.extend(server.poll_accepted());
+            received.extend(server.poll_received());
+
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let (sender, message) = &received[0];
+
+        assert_eq!(accepted, vec![*sender]);
+        assert_eq!(message.as_any().downcast_ref::<HeartbeatPing>().unwrap().sent_at, 42);
+
+        server.shutdown();
+    }
+}
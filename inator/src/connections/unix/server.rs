@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::fs::{remove_file, set_permissions, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use crate::connections::{BIND_RETRY_INTERVAL, BytesOptions, Connection, ConnectionsType, MessageChannel, OrderOptions};
+use crate::connections::tcp::connection::{TcpConnection, TcpTransportStream, DEFAULT_MAX_FRAME_SIZE};
+use crate::connections::transport::{ServerTransport, TransportState};
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+pub struct ServerUnixSettings {
+    pub(crate) path: PathBuf,
+    pub(crate) bytes: BytesOptions,
+    pub(crate) order: OrderOptions,
+    pub(crate) max_connections: usize,
+    pub(crate) max_frame_size: usize,
+    pub(crate) permissions: Option<u32>
+}
+
+pub struct ServerUnixConnection {
+    pub(crate) settings: ServerUnixSettings,
+    pub(crate) name: &'static str,
+    pub(crate) listener: Option<Arc<UnixListener>>,
+    pub(crate) started: bool,
+    pub(crate) bind_retry_at: Option<Instant>,
+    pub(crate) runtime: Option<Handle>,
+    pub(crate) dropped: Arc<AtomicBool>,
+    pub(crate) cancel_token: Arc<CancellationToken>,
+    pub(crate) connection_down_sender: Arc<UnboundedSender<()>>,
+    pub(crate) connection_down_receiver: UnboundedReceiver<()>,
+    pub(crate) connection_up_sender: Arc<UnboundedSender<Arc<UnixListener>>>,
+    pub(crate) connection_up_receiver: UnboundedReceiver<Arc<UnixListener>>,
+    pub(crate) client_connected_sender: Arc<UnboundedSender<UnixStream>>,
+    pub(crate) client_connected_receiver: UnboundedReceiver<UnixStream>,
+    pub(crate) connections: HashMap<Uuid, TcpConnection>
+}
+
+impl Default for ServerUnixSettings {
+    fn default() -> Self {
+        ServerUnixSettings {
+            path: std::env::temp_dir().join("inator.sock"),
+            bytes: BytesOptions::U32,
+            order: OrderOptions::LittleEndian,
+            max_connections: 0,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            permissions: None
+        }
+    }
+}
+
+impl ServerUnixSettings {
+    pub fn new(path: impl Into<PathBuf>, bytes: BytesOptions, order: OrderOptions, max_connections: usize) -> Self {
+        Self {
+            path: path.into(),
+            bytes,
+            order,
+            max_connections,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            permissions: None
+        }
+    }
+
+    /// Frames announcing more bytes than this are rejected and the peer is disconnected,
+    /// by default `DEFAULT_MAX_FRAME_SIZE`.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// File mode set on the socket after binding, e.g. `0o660` to only let the owner and group connect.
+    pub fn with_permissions(mut self, mode: u32) -> Self {
+        self.permissions = Some(mode);
+        self
+    }
+}
+
+impl ServerUnixConnection {
+    pub fn new(settings: ServerUnixSettings, name: &'static str) -> ServerUnixConnection {
+        let (connection_down_sender, connection_down_receiver) = unbounded_channel::<()>();
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<Arc<UnixListener>>();
+        let (client_connected_sender, client_connected_receiver) = unbounded_channel::<UnixStream>();
+
+        ServerUnixConnection {
+            settings,
+            name,
+            listener: None,
+            started: false,
+            bind_retry_at: None,
+            runtime: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+            cancel_token: Arc::new(CancellationToken::new()),
+            connection_down_sender: Arc::new(connection_down_sender),
+            connection_down_receiver,
+            connection_up_sender: Arc::new(connection_up_sender),
+            connection_up_receiver,
+            client_connected_sender: Arc::new(client_connected_sender),
+            client_connected_receiver,
+            connections: HashMap::new()
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.settings.max_connections > 0 && self.connections.len() >= self.settings.max_connections
+    }
+
+    fn remove_socket_file(&self) {
+        if self.settings.path.exists() {
+            let _ = remove_file(&self.settings.path);
+        }
+    }
+}
+
+impl Connection for ServerUnixConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        if self.bind_retry_at.is_some_and(|retry_at| Instant::now() < retry_at) {return;}
+
+        let settings = &self.settings;
+
+        // A socket file left behind by a previous run would make the bind fail.
+        self.remove_socket_file();
+
+        // Binding registers the listener with the reactor of the network runtime.
+        let _runtime_guard = self.runtime.get_or_insert_with(network_runtime).enter();
+
+        let listener = match UnixListener::bind(&settings.path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Error on bind {:?}: {}, trying again...", settings.path, e);
+                self.bind_retry_at = Some(Instant::now() + BIND_RETRY_INTERVAL);
+                return;
+            }
+        };
+
+        if let Some(mode) = settings.permissions
+            && let Err(e) = set_permissions(&settings.path, Permissions::from_mode(mode)) {
+            eprintln!("Failed to set permissions of {:?}: {}", settings.path, e);
+        }
+
+        println!("Server unix socket binded successfully!");
+
+        let unix_listener = Arc::new(listener);
+        let dropped = Arc::clone(&self.dropped);
+        let connection_up_sender = Arc::clone(&self.connection_up_sender);
+        let connection_down_sender = Arc::clone(&self.connection_down_sender);
+        let client_connected_sender = Arc::clone(&self.client_connected_sender);
+        let cancel_token = Arc::clone(&self.cancel_token);
+
+        self.started = true;
+
+        self.runtime.as_ref().unwrap().spawn(async move {
+            dropped.store(false, Ordering::SeqCst);
+
+            connection_up_sender.send(Arc::clone(&unix_listener)).unwrap();
+
+            loop {
+                tokio::select! {
+                    _ = cancel_token.cancelled() => {
+                        break;
+                    },
+                    accept_result = unix_listener.accept() => {
+                        match accept_result {
+                            Ok((stream, _)) => {
+                                println!("Accepted unix socket connection");
+
+                                client_connected_sender.send(stream).unwrap();
+                            },
+                            Err(e) => {
+                                eprintln!("Error on accept: {:?}", e);
+
+                                connection_down_sender.send(()).unwrap();
+
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                if dropped.load(Ordering::SeqCst) {
+                    break;
+                }
+            }
+        });
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            drop(listener);
+        }
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        self.runtime.take();
+
+        if let Some(listener) = self.listener.take() {
+            drop(listener);
+        }
+
+        self.remove_socket_file();
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ServerTransport for ServerUnixConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::Unix
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.listener.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok(unix_listener) = self.connection_up_receiver.try_recv() {
+            self.listener = Some(unix_listener);
+        }
+
+        if self.connection_down_receiver.try_recv().is_ok() {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_accepted(&mut self) -> Vec<Uuid> {
+        let mut accepted = Vec::new();
+
+        while let Ok(unix_stream) = self.client_connected_receiver.try_recv() {
+            if self.is_full() {
+                println!("Connection {} is full, rejecting unix socket connection", self.name);
+                continue
+            }
+
+            let settings = &self.settings;
+            // A token per client, so `disconnect_client` stops the tasks of that client only.
+            let mut unix_connection = TcpConnection::new(TcpTransportStream::Unix(unix_stream), self.name, NetworkSide::Server, Arc::new(self.cancel_token.child_token()),settings.bytes,settings.order,settings.max_frame_size);
+            let current_uuid = unix_connection.uuid.unwrap();
+
+            unix_connection.start_listening(self.runtime.as_ref().unwrap());
+
+            self.connections.insert(current_uuid,unix_connection);
+            accepted.push(current_uuid);
+        }
+
+        accepted
+    }
+
+    fn poll_disconnected(&mut self) -> Vec<Uuid> {
+        let mut disconnected = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            if client_connection.connection_down_receiver.try_recv().is_ok() {
+                client_connection.listening = false;
+
+                disconnected.push(*uuid);
+            }
+        }
+
+        for uuid in disconnected.iter() {
+            self.connections.remove(uuid);
+        }
+
+        disconnected
+    }
+
+    fn queue_depth(&self, client: &Uuid) -> Option<usize> {
+        self.connections.get(client).map(|client_connection| client_connection.queue_depth())
+    }
+
+    fn poll_rejected(&mut self) -> Vec<Uuid> {
+        let mut rejected = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            if client_connection.frame_rejected_receiver.try_recv().is_ok() {
+                rejected.push(*uuid);
+            }
+        }
+
+        rejected
+    }
+
+    fn poll_received(&mut self) -> Vec<(Uuid, Box<dyn MessageTrait>)> {
+        let mut received = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            while let Ok(message) = client_connection.message_received_receiver.try_recv() {
+                received.push((*uuid, message));
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, client: &Uuid, message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+        match self.connections.get_mut(client) {
+            Some(client_connection) => {
+                client_connection.send_message(message);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn disconnect_client(&mut self, client: &Uuid) {
+        if let Some(mut client_connection) = self.connections.remove(client) {
+            client_connection.shutdown();
+            client_connection.cancellation_token.cancel();
+        }
+    }
+
+    fn clients(&self) -> Vec<Uuid> {
+        self.connections.keys().copied().collect()
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
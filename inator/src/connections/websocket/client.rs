@@ -0,0 +1,209 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+use crate::connections::{Connection, ConnectionsType, MessageChannel};
+use crate::connections::transport::{ClientTransport, TransportState};
+use crate::connections::websocket::connection::{WebSocketConnection, WebSocketRuntime};
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+pub struct ClientWebSocketSettings {
+    pub(crate) url: String
+}
+
+pub struct ClientWebSocketConnection {
+    pub(crate) settings: ClientWebSocketSettings,
+    pub(crate) name: &'static str,
+    pub(crate) started: bool,
+    pub(crate) connected: bool,
+    pub(crate) runtime: Option<WebSocketRuntime>,
+    pub(crate) dropped: Arc<AtomicBool>,
+    pub(crate) local_websocket_connection: Option<WebSocketConnection>,
+    pub(crate) cancel_token: Arc<CancellationToken>,
+    pub(crate) connection_up_sender: Arc<UnboundedSender<()>>,
+    pub(crate) connection_up_receiver: UnboundedReceiver<()>
+}
+
+impl Default for ClientWebSocketSettings {
+    fn default() -> Self {
+        ClientWebSocketSettings {
+            url: "ws://127.0.0.1:8082".to_string()
+        }
+    }
+}
+
+impl ClientWebSocketSettings {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string()
+        }
+    }
+}
+
+impl ClientWebSocketConnection {
+    pub fn new(settings: ClientWebSocketSettings, name: &'static str) -> ClientWebSocketConnection {
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<()>();
+
+        ClientWebSocketConnection {
+            settings,
+            name,
+            started: false,
+            connected: false,
+            runtime: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+            local_websocket_connection: None,
+            cancel_token: Arc::new(CancellationToken::new()),
+            connection_up_sender: Arc::new(connection_up_sender),
+            connection_up_receiver
+        }
+    }
+}
+
+impl Connection for ClientWebSocketConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        let url = self.settings.url.clone();
+        let dropped = Arc::clone(&self.dropped);
+        let cancel_token = Arc::clone(&self.cancel_token);
+        let connection_up_sender = Arc::clone(&self.connection_up_sender);
+        let mut websocket_connection = WebSocketConnection::new(self.name, NetworkSide::Client, Arc::clone(&self.cancel_token));
+        let pumps = match websocket_connection.take_pumps() {
+            Some(pumps) => pumps,
+            None => return,
+        };
+
+        self.started = true;
+        self.connected = false;
+        self.local_websocket_connection = Some(websocket_connection);
+        self.dropped.store(false, Ordering::SeqCst);
+
+        self.runtime.get_or_insert_with(WebSocketRuntime::new).spawn(async move {
+            let websocket_stream = match tokio_tungstenite_wasm::connect(url.as_str()).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("Failed to connect to server: {}", e);
+                    dropped.store(true, Ordering::SeqCst);
+                    return;
+                }
+            };
+
+            if cancel_token.is_cancelled() {
+                return;
+            }
+
+            let _ = connection_up_sender.send(());
+
+            pumps.run_client(websocket_stream).await;
+        });
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(local_websocket_connection) = self.local_websocket_connection.take() {
+            drop(local_websocket_connection);
+        }
+
+        self.cancel_token.cancel();
+        self.cancel_token = Arc::new(CancellationToken::new());
+        self.dropped.store(true, Ordering::SeqCst);
+        self.started = false;
+        self.connected = false;
+    }
+
+    fn disconnect(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown();
+        }
+
+        if let Some(local_websocket_connection) = self.local_websocket_connection.take() {
+            drop(local_websocket_connection);
+        }
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ClientTransport for ClientWebSocketConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::WebSocket
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(WebSocketRuntime::from_handle(runtime));
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.connected {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if self.connection_up_receiver.try_recv().is_ok() {
+            self.connected = true;
+        } else if self.started && !self.connected && self.dropped.load(Ordering::SeqCst) {
+            self.started = false;
+            self.local_websocket_connection = None;
+        }
+
+        let connection_down = match self.local_websocket_connection.as_mut() {
+            Some(local_websocket_connection) => local_websocket_connection.connection_down_receiver.try_recv().is_ok(),
+            None => false
+        };
+
+        if connection_down {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_received(&mut self) -> Vec<Box<dyn MessageTrait>> {
+        let mut received = Vec::new();
+
+        if !self.connected {return received}
+
+        if let Some(local_websocket_connection) = self.local_websocket_connection.as_mut() {
+            while let Ok(message) = local_websocket_connection.message_received_receiver.try_recv() {
+                received.push(message);
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+        if !self.connected {return false}
+
+        match self.local_websocket_connection.as_mut() {
+            Some(local_websocket_connection) => {
+                local_websocket_connection.send_message(message);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.cancel_connection()
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
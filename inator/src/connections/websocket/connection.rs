@@ -0,0 +1,246 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use futures_util::{SinkExt, StreamExt};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::{deserialize_message, encode_message, MessageTrait};
+
+/// Spawns on the shared `NetworkRuntime` natively and on the browser's event loop on wasm.
+pub struct WebSocketRuntime {
+    #[cfg(not(target_arch = "wasm32"))]
+    runtime: Handle
+}
+
+pub struct WebSocketConnection {
+    pub connection_name: &'static str,
+    pub network_side: NetworkSide,
+    pub uuid: Option<Uuid>,
+    pub socket_addr: Option<SocketAddr>,
+    pub cancellation_token: Arc<CancellationToken>,
+    pub connection_down_sender: Arc<UnboundedSender<()>>,
+    pub connection_down_receiver: UnboundedReceiver<()>,
+    pub message_received_sender: Arc<UnboundedSender<Box<dyn MessageTrait>>>,
+    pub message_received_receiver: UnboundedReceiver<Box<dyn MessageTrait>>,
+    pub listening: bool,
+    pub(crate) outgoing_sender: UnboundedSender<Vec<u8>>,
+    pub(crate) outgoing_receiver: Option<UnboundedReceiver<Vec<u8>>>
+}
+
+pub(crate) struct WebSocketPumps {
+    connection_down_sender: Arc<UnboundedSender<()>>,
+    message_received_sender: Arc<UnboundedSender<Box<dyn MessageTrait>>>,
+    cancellation_token: Arc<CancellationToken>,
+    network_side: NetworkSide,
+    outgoing_receiver: UnboundedReceiver<Vec<u8>>
+}
+
+impl WebSocketRuntime {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new() -> Self {
+        WebSocketRuntime {
+            runtime: network_runtime()
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_handle(runtime: Handle) -> Self {
+        WebSocketRuntime {
+            runtime
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn new() -> Self {
+        WebSocketRuntime {}
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spawn<F: Future<Output = ()> + Send + 'static>(&self, future: F) {
+        self.runtime.spawn(future);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn spawn<F: Future<Output = ()> + 'static>(&self, future: F) {
+        wasm_bindgen_futures::spawn_local(future);
+    }
+
+    /// The tasks themselves stop through their cancellation tokens, the runtime is shared.
+    pub fn shutdown(self) {}
+}
+
+impl Default for WebSocketRuntime {
+    fn default() -> Self {
+        WebSocketRuntime::new()
+    }
+}
+
+impl WebSocketConnection {
+    pub fn new(connection_name: &'static str, network_side: NetworkSide, cancellation_token: Arc<CancellationToken>) -> Self {
+        let (connection_down_sender, connection_down_receiver) = unbounded_channel::<()>();
+        let (message_received_sender, message_received_receiver) = unbounded_channel::<Box<dyn MessageTrait>>();
+        let (outgoing_sender, outgoing_receiver) = unbounded_channel::<Vec<u8>>();
+
+        WebSocketConnection {
+            connection_name,
+            network_side,
+            uuid: if network_side == NetworkSide::Server {Some(Uuid::new_v4())} else {None},
+            socket_addr: None,
+            cancellation_token: Arc::clone(&cancellation_token),
+            connection_down_sender: Arc::new(connection_down_sender),
+            connection_down_receiver,
+            message_received_sender: Arc::new(message_received_sender),
+            message_received_receiver,
+            listening: false,
+            outgoing_sender,
+            outgoing_receiver: Some(outgoing_receiver)
+        }
+    }
+
+    pub(crate) fn take_pumps(&mut self) -> Option<WebSocketPumps> {
+        let outgoing_receiver = self.outgoing_receiver.take()?;
+
+        self.listening = true;
+
+        Some(WebSocketPumps {
+            connection_down_sender: Arc::clone(&self.connection_down_sender),
+            message_received_sender: Arc::clone(&self.message_received_sender),
+            cancellation_token: Arc::clone(&self.cancellation_token),
+            network_side: self.network_side,
+            outgoing_receiver
+        })
+    }
+
+    pub fn send_message(&mut self, message: &dyn MessageTrait) {
+        let Some(encoded) = encode_message(message) else {
+            return;
+        };
+
+        if self.outgoing_sender.send(encoded).is_err() {
+            eprintln!("WebSocket writer is closed");
+        }
+    }
+}
+
+impl WebSocketPumps {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn run_server(self, stream: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>) {
+        use tokio_tungstenite::tungstenite::Message;
+
+        let (write_half, read_half) = stream.split();
+        let frames = read_half.filter_map(|frame| async move {
+            match frame {
+                Ok(Message::Binary(bytes)) => Some(Ok(bytes.to_vec())),
+                Ok(Message::Close(_)) => Some(Err(())),
+                Ok(_) => None,
+                Err(e) => {
+                    eprintln!("Failed to read frame: {:?}", e);
+                    Some(Err(()))
+                }
+            }
+        });
+        let write_half = write_half.with(|bytes: Vec<u8>| async move {
+            Ok::<Message, tokio_tungstenite::tungstenite::Error>(Message::binary(bytes))
+        });
+
+        self.run(frames, write_half).await;
+    }
+
+    pub(crate) async fn run_client(self, stream: tokio_tungstenite_wasm::WebSocketStream) {
+        use tokio_tungstenite_wasm::Message;
+
+        let (write_half, read_half) = stream.split();
+        let frames = read_half.filter_map(|frame| async move {
+            match frame {
+                Ok(Message::Binary(bytes)) => Some(Ok(bytes.to_vec())),
+                Ok(Message::Close(_)) => Some(Err(())),
+                Ok(_) => None,
+                Err(e) => {
+                    eprintln!("Failed to read frame: {:?}", e);
+                    Some(Err(()))
+                }
+            }
+        });
+        let write_half = write_half.with(|bytes: Vec<u8>| async move {
+            Ok::<Message, tokio_tungstenite_wasm::Error>(Message::binary(bytes))
+        });
+
+        self.run(frames, write_half).await;
+    }
+
+    async fn run<S, W, E>(self, frames: S, write_half: W)
+    where
+        S: futures_util::Stream<Item = Result<Vec<u8>, ()>>,
+        W: futures_util::Sink<Vec<u8>, Error = E>,
+        E: std::fmt::Debug,
+    {
+        let WebSocketPumps {
+            connection_down_sender,
+            message_received_sender,
+            cancellation_token,
+            network_side,
+            mut outgoing_receiver
+        } = self;
+
+        let read = async {
+            let mut frames = std::pin::pin!(frames);
+
+            loop {
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+
+                    frame = frames.next() => {
+                        match frame {
+                            Some(Ok(bytes)) => {
+                                if let Some(message) = deserialize_message(&bytes) {
+                                    let _ = message_received_sender.send(message);
+                                } else {
+                                    eprintln!("Message not registered or failed to deserialize");
+                                }
+                            }
+                            Some(Err(_)) | None => {
+                                println!("WebSocket closed from {:?}", network_side);
+
+                                let _ = connection_down_sender.send(());
+
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        let write = async {
+            let mut write_half = std::pin::pin!(write_half);
+
+            loop {
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+
+                    bytes = outgoing_receiver.recv() => {
+                        let Some(bytes) = bytes else {
+                            break;
+                        };
+
+                        if let Err(e) = write_half.send(bytes).await {
+                            eprintln!("Failed to send message: {:?}", e);
+                            eprintln!("From {:?}", network_side);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            let _ = write_half.close().await;
+        };
+
+        tokio::join!(read, write);
+    }
+}
@@ -0,0 +1,4 @@
+﻿#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+pub mod client;
+pub mod connection;
\ No newline at end of file
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::{accept_async, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use crate::connections::{BIND_RETRY_INTERVAL, Connection, ConnectionsType, MessageChannel};
+use crate::connections::transport::{ServerTransport, TransportState};
+use crate::connections::websocket::connection::WebSocketConnection;
+use crate::connections::runtime::network_runtime;
+use crate::NetworkSide;
+use crate::systems::messaging::MessageTrait;
+
+pub struct ServerWebSocketSettings {
+    pub(crate) address: IpAddr,
+    pub(crate) port: u16,
+    pub(crate) max_connections: usize
+}
+
+pub struct ServerWebSocketConnection {
+    pub(crate) settings: ServerWebSocketSettings,
+    pub(crate) name: &'static str,
+    pub(crate) listener: Option<Arc<TcpListener>>,
+    pub(crate) started: bool,
+    pub(crate) runtime: Option<Handle>,
+    pub(crate) dropped: Arc<AtomicBool>,
+    pub(crate) cancel_token: Arc<CancellationToken>,
+    pub(crate) connection_down_sender: Arc<UnboundedSender<()>>,
+    pub(crate) connection_down_receiver: UnboundedReceiver<()>,
+    pub(crate) connection_up_sender: Arc<UnboundedSender<Arc<TcpListener>>>,
+    pub(crate) connection_up_receiver: UnboundedReceiver<Arc<TcpListener>>,
+    pub(crate) client_connected_sender: Arc<UnboundedSender<(WebSocketStream<TcpStream>, SocketAddr)>>,
+    pub(crate) client_connected_receiver: UnboundedReceiver<(WebSocketStream<TcpStream>, SocketAddr)>,
+    pub(crate) connections: HashMap<Uuid, WebSocketConnection>
+}
+
+impl Default for ServerWebSocketSettings {
+    fn default() -> Self {
+        ServerWebSocketSettings {
+            address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            port: 8082,
+            max_connections: 0
+        }
+    }
+}
+
+impl ServerWebSocketSettings {
+    pub fn new(address: IpAddr, port: u16, max_connections: usize) -> Self {
+        Self {
+            address,
+            port,
+            max_connections
+        }
+    }
+}
+
+impl ServerWebSocketConnection {
+    pub fn new(settings: ServerWebSocketSettings, name: &'static str) -> ServerWebSocketConnection {
+        let (connection_down_sender, connection_down_receiver) = unbounded_channel::<()>();
+        let (connection_up_sender, connection_up_receiver) = unbounded_channel::<Arc<TcpListener>>();
+        let (client_connected_sender, client_connected_receiver) = unbounded_channel::<(WebSocketStream<TcpStream>, SocketAddr)>();
+
+        ServerWebSocketConnection {
+            settings,
+            name,
+            listener: None,
+            started: false,
+            runtime: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+            cancel_token: Arc::new(CancellationToken::new()),
+            connection_down_sender: Arc::new(connection_down_sender),
+            connection_down_receiver,
+            connection_up_sender: Arc::new(connection_up_sender),
+            connection_up_receiver,
+            client_connected_sender: Arc::new(client_connected_sender),
+            client_connected_receiver,
+            connections: HashMap::new()
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.settings.max_connections > 0 && self.connections.len() >= self.settings.max_connections
+    }
+}
+
+impl Connection for ServerWebSocketConnection {
+    fn start_connection(&mut self) {
+        if !self.can_start() {return;}
+
+        let settings = &self.settings;
+        let address = (settings.address, settings.port);
+        let dropped = Arc::clone(&self.dropped);
+        let connection_up_sender = Arc::clone(&self.connection_up_sender);
+        let connection_down_sender = Arc::clone(&self.connection_down_sender);
+        let client_connected_sender = Arc::clone(&self.client_connected_sender);
+        let cancel_token = Arc::clone(&self.cancel_token);
+
+        self.started = true;
+
+        self.runtime.get_or_insert_with(network_runtime).spawn(async move {
+            dropped.store(false, Ordering::SeqCst);
+
+            let tcp_listener = loop {
+                match TcpListener::bind(address).await {
+                    Ok(listener) => break Arc::new(listener),
+                    Err(e) => {
+                        println!("Error on bind: {}, trying again...", e);
+
+                        if dropped.load(Ordering::SeqCst) {
+                            return;
+                        }
+
+                        tokio::time::sleep(BIND_RETRY_INTERVAL).await;
+                    }
+                };
+            };
+
+            if dropped.load(Ordering::SeqCst) {
+                drop(tcp_listener);
+                return;
+            }
+
+            println!("Server websocket binded successfully!");
+
+            connection_up_sender.send(Arc::clone(&tcp_listener)).unwrap();
+
+            loop {
+                tokio::select! {
+                    _ = cancel_token.cancelled() => {
+                        break;
+                    },
+                    accept_result = tcp_listener.accept() => {
+                        match accept_result {
+                            Ok((stream, addr)) => {
+                                let client_connected_sender = Arc::clone(&client_connected_sender);
+
+                                tokio::spawn(async move {
+                                    match accept_async(stream).await {
+                                        Ok(websocket_stream) => {
+                                            println!("Accepted connection from {}", addr);
+
+                                            let _ = client_connected_sender.send((websocket_stream, addr));
+                                        }
+                                        Err(e) => {
+                                            eprintln!("WebSocket handshake failed with {}: {:?}", addr, e);
+                                        }
+                                    }
+                                });
+                            },
+                            Err(e) => {
+                                eprintln!("Error on accept: {:?}", e);
+
+                                match e.kind() {
+                                    std::io::ErrorKind::ConnectionAborted => {
+                                         println!("Listener aborted (network down or aborted by OS)");
+
+                                         connection_down_sender.send(()).unwrap();
+
+                                         break;
+                                    },
+                                    std::io::ErrorKind::Other => {
+                                         println!("Listener was probably closed manually");
+
+                                         connection_down_sender.send(()).unwrap();
+
+                                         break;
+                                    },
+                                    _ => {
+                                        println!("Unexpected error: {:?}", e.kind());
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if dropped.load(Ordering::SeqCst) {
+                    drop(tcp_listener);
+                    break;
+                }
+            }
+        });
+    }
+
+    fn can_start(&self) -> bool {
+        !&self.started
+    }
+
+    fn cancel_connection(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            drop(listener);
+        }
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+        self.started = false;
+    }
+
+    fn disconnect(&mut self) {
+        self.runtime.take();
+
+        if let Some(listener) = self.listener.take() {
+            drop(listener);
+        }
+
+        self.cancel_token.cancel();
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ServerTransport for ServerWebSocketConnection {
+    fn kind(&self) -> ConnectionsType {
+        ConnectionsType::WebSocket
+    }
+
+    fn set_runtime(&mut self, runtime: Handle) {
+        self.runtime = Some(runtime);
+    }
+
+    fn start(&mut self) {
+        self.start_connection()
+    }
+
+    fn state(&self) -> TransportState {
+        if self.listener.is_some() {
+            TransportState::Connected
+        } else if self.started {
+            TransportState::Connecting
+        } else {
+            TransportState::Stopped
+        }
+    }
+
+    fn update(&mut self) {
+        if let Ok(tcp_listener) = self.connection_up_receiver.try_recv() {
+            self.listener = Some(tcp_listener);
+        }
+
+        if self.connection_down_receiver.try_recv().is_ok() {
+            self.cancel_connection()
+        }
+    }
+
+    fn poll_accepted(&mut self) -> Vec<Uuid> {
+        let mut accepted = Vec::new();
+
+        while let Ok((websocket_stream, socket_addr)) = self.client_connected_receiver.try_recv() {
+            if self.is_full() {
+                println!("Connection is full, rejecting connection to {}", socket_addr);
+                continue
+            }
+
+            let mut websocket_connection = WebSocketConnection::new(self.name, NetworkSide::Server, Arc::clone(&self.cancel_token));
+            let current_uuid = websocket_connection.uuid.unwrap();
+
+            websocket_connection.socket_addr = Some(socket_addr);
+
+            if let Some(pumps) = websocket_connection.take_pumps() {
+                self.runtime.as_ref().unwrap().spawn(pumps.run_server(websocket_stream));
+            }
+
+            self.connections.insert(current_uuid,websocket_connection);
+            accepted.push(current_uuid);
+        }
+
+        accepted
+    }
+
+    fn poll_disconnected(&mut self) -> Vec<Uuid> {
+        let mut disconnected = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            if client_connection.connection_down_receiver.try_recv().is_ok() {
+                client_connection.listening = false;
+
+                disconnected.push(*uuid);
+            }
+        }
+
+        for uuid in disconnected.iter() {
+            self.connections.remove(uuid);
+        }
+
+        disconnected
+    }
+
+    fn poll_received(&mut self) -> Vec<(Uuid, Box<dyn MessageTrait>)> {
+        let mut received = Vec::new();
+
+        for (uuid,client_connection) in self.connections.iter_mut() {
+            while let Ok(message) = client_connection.message_received_receiver.try_recv() {
+                received.push((*uuid, message));
+            }
+        }
+
+        received
+    }
+
+    fn send(&mut self, client: &Uuid, message: &dyn MessageTrait, _channel: MessageChannel) -> bool {
+        match self.connections.get_mut(client) {
+            Some(client_connection) => {
+                client_connection.send_message(message);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn disconnect_client(&mut self, client: &Uuid) {
+        self.connections.remove(client);
+    }
+
+    fn clients(&self) -> Vec<Uuid> {
+        self.connections.keys().copied().collect()
+    }
+
+    fn client_address(&self, client: &Uuid) -> Option<SocketAddr> {
+        self.connections.get(client).and_then(|client_connection| client_connection.socket_addr)
+    }
+
+    fn shutdown(&mut self) {
+        self.disconnect()
+    }
+}
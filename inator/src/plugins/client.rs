@@ -1,130 +1,270 @@
-﻿use std::any::Any;
+﻿use std::collections::HashMap;
 use std::sync::Arc;
 use bevy::app::App;
-use bevy::prelude::{Commands, First, IntoScheduleConfigs, Last, Plugin, ResMut, Update, World};
-use crate::connections::{ClientConnectionType, ClientConnections, Connection, Connections, ConnectionsType};
-use crate::connections::tcp::connection::TcpConnection;
+use bevy::log::{info, warn};
+use bevy::prelude::{AppExtStates, Commands, First, IntoScheduleConfigs, Last, MessageWriter, NextState, Plugin, Res, ResMut, State, Update};
+use crate::connections::{ClientConnectionState, ClientConnections};
+use crate::connections::heartbeat::{HeartbeatPing, HeartbeatPong, LinkHealth};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connections::runtime::{init_network_runtime, NetworkRuntime};
+use crate::connections::session::{ResumeToken, SessionRequest};
+use crate::connections::transport::TransportState;
 use crate::NetworkSide;
-use crate::plugins::ConnectedMessage;
-use crate::systems::messaging::{register_message_type, DISPATCHERS};
+use crate::plugins::{ConnectedMessage, ConnectedToServer, ConnectionAttempt, ConnectionFailed, ConnectionGaveUp, DisconnectMessage, DisconnectReason, DisconnectedFromServer, Reconnected};
+use crate::systems::batching::unbatch;
+use crate::systems::messaging::{queue_message_dispatch, register_message_type, MessageDispatchers};
+use crate::systems::protocol::ProtocolSchema;
 
 pub struct ClientPlugin;
 
+/// Mirrors the state of one client connection into the `ClientConnectionState` Bevy state,
+/// so systems can run on `OnEnter(ClientConnectionState::Connected)`. Needs Bevy's `StatesPlugin`.
+pub struct ClientStatePlugin(pub &'static str);
+
 impl Plugin for ClientPlugin {
     fn build(&self, app: &mut App) {
         register_message_type::<ConnectedMessage>(app, &NetworkSide::Client);
+        #[cfg(not(target_arch = "wasm32"))]
+        init_network_runtime(app);
+
+        let routes = Arc::clone(&app.world().resource::<MessageDispatchers>().1);
 
-        app.insert_resource(ClientConnections::new());
+        app.insert_resource(ClientConnections(HashMap::new(), routes));
+        app.add_message::<ConnectedToServer>();
+        app.add_message::<DisconnectedFromServer>();
+        app.add_message::<ConnectionFailed>();
+        app.add_message::<ConnectionAttempt>();
+        app.add_message::<Reconnected>();
+        app.add_message::<ConnectionGaveUp>();
         app.add_systems(First,start_connections);
-        app.add_systems(Update,check_new_messages);
-        app.add_systems(Last,(check_connection_up,restart_connection).chain());
+        app.add_systems(Update,(check_new_messages,check_heartbeats).chain());
+        app.add_systems(Last,update_connections);
+    }
+}
+
+impl Plugin for ClientStatePlugin {
+    fn build(&self, app: &mut App) {
+        let name = self.0;
+
+        app.init_state::<ClientConnectionState>();
+        app.add_systems(Last,(move |client_connections: Res<ClientConnections>, state: Res<State<ClientConnectionState>>, next_state: ResMut<NextState<ClientConnectionState>>| {
+            sync_client_state(name, client_connections, state, next_state)
+        }).after(update_connections));
     }
 }
 
 pub fn start_connections(
     mut client_connections: ResMut<ClientConnections>,
+    mut connection_attempt: MessageWriter<ConnectionAttempt>,
+    mut connection_failed: MessageWriter<ConnectionFailed>,
+    mut connection_gave_up: MessageWriter<ConnectionGaveUp>,
+    #[cfg(not(target_arch = "wasm32"))]
+    network_runtime: Res<NetworkRuntime>,
 ){
-    for (_,connection) in client_connections.0.iter_mut() {
-        match connection {
-            ClientConnectionType::Tcp(connection) => {
-                connection.start_connection()
+    for connection in client_connections.0.values_mut() {
+        if connection.transport.state() != TransportState::Stopped {
+            continue
+        }
+
+        let reconnect_state = &mut connection.reconnect_state;
+
+        if reconnect_state.attempting() {
+            connection_failed.write(ConnectionFailed(connection.name, reconnect_state.attempts()));
+
+            if reconnect_state.attempt_failed(&connection.reconnect) {
+                warn!("Connection {} gave up after {} attempts", connection.name, reconnect_state.attempts());
+
+                connection_gave_up.write(ConnectionGaveUp(connection.name, reconnect_state.attempts()));
             }
+
+            continue
+        }
+
+        if !reconnect_state.ready() {
+            continue
         }
+
+        connection_attempt.write(ConnectionAttempt(connection.name, reconnect_state.attempt_started()));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        connection.transport.set_runtime(network_runtime.handle().clone());
+        connection.transport.start();
     }
 }
 
 pub fn check_new_messages(
     mut client_connections: ResMut<ClientConnections>,
+    mut connected_to_server: MessageWriter<ConnectedToServer>,
+    mut reconnected: MessageWriter<Reconnected>,
     mut commands: Commands,
 ){
-    for (_,connection) in client_connections.0.iter_mut() {
-        match connection {
-            ClientConnectionType::Tcp(connection) => {
-                match connection.local_tcp_connection.as_mut() {
-                    Some(local_tcp_connection) => {
-                        match local_tcp_connection.message_received_receiver.try_recv() {
-                            Ok(message) => {
-                                let connection_name = connection.name;
-                                
-                                if let Some(connected_message) = message.as_any().downcast_ref::<ConnectedMessage>() {
-                                    println!("connected message");
-                                    local_tcp_connection.uuid = Some(connected_message.uuid);
-                                }
-                                
-                                let uuid = local_tcp_connection.uuid;
-                                
-                                commands.queue(move |w: &mut World| {
-                                    let type_id = message.as_any().type_id();
-                                    let map = DISPATCHERS.lock().unwrap();
-                                    if let Some(dispatcher) = map.get(&type_id) {
-                                        let boxed_any = message as Box<dyn Any>;
-
-                                        dispatcher(boxed_any, w, ConnectionsType::Tcp, uuid, &NetworkSide::Client, connection_name);
-                                    } else {
-                                        println!("This message does not exist");
-                                    }
-                                });
-                            },
-                            Err(_) => continue,
-                        }
-                    }
-                    None => {
-                        continue
-                    }
+    for connection in client_connections.0.values_mut() {
+        for message in connection.transport.poll_received().into_iter().flat_map(unbatch) {
+            if let Some(link) = connection.link.as_mut() {
+                link.received();
+            }
+
+            if !connection.routes.read().unwrap().direction(message.as_ref()).from_server() {
+                connection.protocol_violations += 1;
+                warn!("Server sent a message only clients can send on {}", connection.name);
+
+                continue
+            }
+
+            if let Some(ping) = message.as_any().downcast_ref::<HeartbeatPing>() {
+                connection.transmit(&HeartbeatPong{
+                    sent_at: ping.sent_at
+                });
+
+                continue
+            }
+
+            if let Some(pong) = message.as_any().downcast_ref::<HeartbeatPong>() {
+                if let Some(link) = connection.link.as_mut() {
+                    link.pong_received(pong);
+                }
+
+                continue
+            }
+
+            if let Some(disconnect_message) = message.as_any().downcast_ref::<DisconnectMessage>() {
+                info!("Connection {} was closed by the server: {:?}", connection.name, disconnect_message.reason);
+
+                connection.disconnect_reason = Some(disconnect_message.reason.clone());
+                connection.session = None;
+
+                if disconnect_message.reason.is_terminal() {
+                    connection.reconnect_state.stop();
+                }
+
+                connection.transport.cancel();
+
+                break
+            }
+
+            if let Some(connected_message) = message.as_any().downcast_ref::<ConnectedMessage>() {
+                connected_to_server.write(ConnectedToServer(connection.name, connected_message.uuid));
+
+                if connection.session.is_some() {
+                    reconnected.write(Reconnected(connection.name, connected_message.uuid, connected_message.resumed));
                 }
+
+                connection.uuid = Some(connected_message.uuid);
+                connection.session = Some(ResumeToken {
+                    uuid: connected_message.uuid,
+                    token: connected_message.resume_token
+                });
             }
+
+            queue_message_dispatch(&mut commands, message, connection.transport.kind(), connection.uuid, NetworkSide::Client, connection.name);
         }
     }
 }
 
-pub fn check_connection_up(
+pub fn check_heartbeats(
     mut client_connections: ResMut<ClientConnections>,
 ){
-    for (_,connection) in client_connections.0.iter_mut() {
-        match connection {
-            ClientConnectionType::Tcp(connection) => {
-                match connection.connection_up_receiver.try_recv() {
-                    Ok(tcp_stream) => {
-                        if let Some(local_tcp_connection) = connection.local_tcp_connection.take() {
-                            drop(local_tcp_connection);
-                        }
-
-                        let settings = &connection.settings;
-                        let mut tcp_connection = TcpConnection::new(tcp_stream, connection.name, NetworkSide::Client, Arc::clone(&connection.cancel_token),settings.bytes,settings.order);
-                        
-                        tcp_connection.start_listening(connection.runtime.as_ref().unwrap());
-
-                        connection.local_tcp_connection = Some(tcp_connection);
-                    }
-                    Err(_) => {
-                        continue
-                    }
-                }
-            }
+    for connection in client_connections.0.values_mut() {
+        let Some(link) = connection.link.as_mut() else {
+            continue
+        };
+
+        if link.timed_out(&connection.heartbeat) {
+            warn!("Connection {} timed out", connection.name);
+
+            connection.disconnect_reason = Some(DisconnectReason::TimedOut);
+            connection.transport.cancel();
+
+            continue
+        }
+
+        if let Some(ping) = link.ping(&connection.heartbeat) {
+            connection.transmit(&ping);
         }
     }
 }
 
-pub fn restart_connection(
+pub fn update_connections(
     mut client_connections: ResMut<ClientConnections>,
+    protocol_schema: Res<ProtocolSchema>,
+    mut disconnected_from_server: MessageWriter<DisconnectedFromServer>,
 ){
-    for (_,connection) in client_connections.0.iter_mut() {
-        match connection {
-            ClientConnectionType::Tcp(connection) => {
-                match connection.local_tcp_connection.as_mut() {
-                    Some(local_tcp_connection) => {
-                        match local_tcp_connection.connection_down_receiver.try_recv() {
-                            Ok(_) => {
-                                connection.cancel_connection()
-                            }
-                            Err(_) => {}
-                        }
-                    }
-                    None => {
-                        continue
-                    }
-                }
+    for connection in client_connections.0.values_mut() {
+        connection.flush();
+        connection.transport.update();
+
+        if connection.transport.state() != TransportState::Connected {
+            let had_uuid = connection.uuid.take().is_some();
+
+            if let Some(reason) = connection.disconnect_reason.take() {
+                disconnected_from_server.write(DisconnectedFromServer(connection.name, reason));
+            } else if had_uuid {
+                warn!("Connection {} lost the server", connection.name);
+
+                disconnected_from_server.write(DisconnectedFromServer(connection.name, DisconnectReason::ConnectionLost));
             }
+
+            connection.link = None;
+        } else if connection.link.is_none() {
+            connection.link = Some(LinkHealth::new());
+            connection.reconnect_state.connected();
+
+            connection.transmit(&SessionRequest {
+                resume: connection.session,
+                credentials: connection.credentials.clone(),
+                protocol: protocol_schema.info()
+            });
         }
     }
-}
\ No newline at end of file
+}
+
+fn sync_client_state(
+    name: &'static str,
+    client_connections: Res<ClientConnections>,
+    state: Res<State<ClientConnectionState>>,
+    mut next_state: ResMut<NextState<ClientConnectionState>>,
+){
+    let connection_state = client_connections.connection_state(name);
+
+    if *state.get() != connection_state {
+        next_state.set(connection_state);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::plugins::testing::LocalPair;
+
+    fn closed_by_server(reason: DisconnectReason) -> LocalPair {
+        let mut pair = LocalPair::new();
+
+        assert!(pair.connected());
+
+        let uuid = pair.client_connection().uuid().unwrap();
+
+        pair.server_connections().disconnect_client("memory", &uuid, reason);
+
+        assert!(pair.update_until(|pair| pair.client_connection().uuid() != Some(uuid)));
+
+        pair
+    }
+
+    #[test]
+    fn terminal_reasons_stop_reconnecting() {
+        let mut pair = closed_by_server(DisconnectReason::Kicked);
+
+        assert!(pair.client_connection().gave_up());
+        assert!(!pair.update_within(Duration::from_secs(1), |pair| pair.client_connection().uuid().is_some()));
+    }
+
+    #[test]
+    fn server_shutting_down_reconnects() {
+        let mut pair = closed_by_server(DisconnectReason::ServerShuttingDown);
+
+        assert!(!pair.client_connection().gave_up());
+        assert!(pair.connected());
+    }
+}
@@ -1,4 +1,5 @@
-﻿use crate::systems::messaging::MessageTrait;
+﻿use crate::systems::messaging::{MessageDirection, MessageTrait};
+use std::net::SocketAddr;
 use serde::{Deserialize, Serialize} ;
 use uuid::Uuid;
 use message_derive::Message;
@@ -6,8 +7,11 @@ use bevy::prelude::{Message as BevyMessage};
 use crate::connections::ConnectionsType;
 
 pub mod client;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod server;
 pub mod replication;
+#[cfg(test)]
+mod testing;
 
 #[derive(BevyMessage)]
 pub struct ClientConnected(pub Uuid, pub ConnectionsType, pub &'static str);
@@ -15,8 +19,82 @@ pub struct ClientConnected(pub Uuid, pub ConnectionsType, pub &'static str);
 #[derive(BevyMessage)]
 pub struct ClientDiconnected(pub Uuid, pub ConnectionsType, pub &'static str);
 
-#[derive(Serialize, Deserialize, Message)]
+/// A client sent its credentials while the connection uses `Authenticator::Manual`.
+/// `request` is only used to answer with `accept_client` or `reject_client`, the client
+/// gets its real uuid once accepted.
+#[derive(BevyMessage)]
+pub struct AuthRequest {
+    pub connection_name: &'static str,
+    pub request: Uuid,
+    pub credentials: Vec<u8>,
+    pub address: Option<SocketAddr>
+}
+
+/// A client came back within the grace period and kept its uuid.
+#[derive(BevyMessage)]
+pub struct ClientReconnected(pub Uuid, pub ConnectionsType, pub &'static str);
+
+/// The server gave this client its uuid, written again after every reconnect.
+#[derive(BevyMessage)]
+pub struct ConnectedToServer(pub &'static str, pub Uuid);
+
+/// The client lost a connection that had a uuid, or the server closed it with a reason.
+#[derive(BevyMessage)]
+pub struct DisconnectedFromServer(pub &'static str, pub DisconnectReason);
+
+/// The n-th connection attempt in a row ended without reaching the server.
+#[derive(BevyMessage)]
+pub struct ConnectionFailed(pub &'static str, pub u32);
+
+/// The client started its n-th connection attempt in a row.
+#[derive(BevyMessage)]
+pub struct ConnectionAttempt(pub &'static str, pub u32);
+
+/// The client got a uuid again after losing the server, the bool tells if the old session was resumed.
+#[derive(BevyMessage)]
+pub struct Reconnected(pub &'static str, pub Uuid, pub bool);
+
+/// A client sent a message only the server may send, with the number of violations of that client so far.
+#[derive(BevyMessage)]
+pub struct ProtocolViolation(pub &'static str, pub Uuid, pub u32);
+
+/// The client stopped retrying after this many attempts, see `ClientConnections::reconnect`.
+#[derive(BevyMessage)]
+pub struct ConnectionGaveUp(pub &'static str, pub u32);
+
+/// Sent by the server right before it closes a link. `TimedOut` and `ConnectionLost`
+/// are only reported by the client itself.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Kicked,
+    Banned,
+    ServerShuttingDown,
+    VersionMismatch,
+    Unauthorized,
+    ProtocolViolation,
+    TimedOut,
+    ConnectionLost,
+    Custom(String)
+}
+
+impl DisconnectReason {
+    /// Reasons that would happen again on a new connection, the client stops retrying after them.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, DisconnectReason::Kicked | DisconnectReason::Banned | DisconnectReason::Unauthorized | DisconnectReason::VersionMismatch)
+    }
+}
+
+#[derive(Serialize, Deserialize, Message, Clone)]
+#[message(direction = ServerToClient)]
+pub(crate) struct DisconnectMessage {
+    pub reason: DisconnectReason
+}
+
+#[derive(Serialize, Deserialize, Message, Clone)]
+#[message(direction = ServerToClient)]
 pub(crate) struct ConnectedMessage {
-    pub uuid: Uuid
+    pub uuid: Uuid,
+    pub resume_token: Uuid,
+    pub resumed: bool
 }
 
@@ -3,29 +3,33 @@ use std::collections::HashMap;
 use bevy::app::App;
 use bevy::log::error;
 use bevy::prelude::{Added, AppTypeRegistry, Changed, Commands, Component, Entity, Last, MessageReader, ParamSet, Plugin, PostUpdate, Query, Reflect, ReflectComponent, Res, ResMut, Resource, Update, With, Without, World};
-use bevy::reflect::GetTypeRegistration;
+use bevy::reflect::{GetTypeRegistration, TypePath};
 use bincode::config::standard;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 use serde::de::DeserializeOwned;
 use uuid::Uuid;
 use message_derive::Message;
+#[cfg(not(target_arch = "wasm32"))]
 use crate::connections::{ServerConnections};
 use crate::NetworkSide;
-use crate::systems::messaging::{register_message_type, MessageReceivedFromServer, MessageTrait};
+use crate::systems::messaging::{register_message_type, MessageDirection, MessageReceivedFromServer, MessageTrait};
+use crate::systems::protocol::{stable_hash, ProtocolSchema};
 
 pub struct ReplicatingPlugin {
     pub network_side: NetworkSide
 }
 pub struct ReplicationInfo{
     type_id: TypeId,
+    type_path: &'static str,
     deserialize_fn: fn(&String) -> Box<dyn Reflect>,
 }
 
 #[derive(Component)]
 pub struct FirstReplicated;
 
-#[derive(Serialize, Deserialize, Message)]
+#[derive(Serialize, Deserialize, Message, Clone)]
+#[message(direction = ServerToClient)]
 pub struct ReplicateMessageFromServer{
     replicated_byes: Vec<u8>,
     components: HashMap<i32,String>
@@ -43,8 +47,10 @@ pub struct ReplicateTo{
     jsons_datas: HashMap<i32, String>,
 }
 
+/// Component ids come from `ComponentReplicated::replication_id`, so they are the same in every
+/// build whatever order the components are registered in.
 #[derive(Default,Resource)]
-pub struct ReplicationComponentsRegistry(i32, HashMap<TypeId, i32>, HashMap<i32, ReplicationInfo>);
+pub struct ReplicationComponentsRegistry(HashMap<TypeId, i32>, HashMap<i32, ReplicationInfo>);
 #[derive(Default,Resource)]
 pub struct ServerReplicationQueue(HashMap<Entity, ReplicateTo>);
 #[derive(Default,Resource)]
@@ -52,7 +58,20 @@ pub struct ReplicatedEntities(HashMap<Uuid, Entity>);
 #[derive(Default,Resource)]
 pub struct NewClientsToReplicate(pub(crate) Vec<Uuid>);
 
-pub trait ComponentReplicated: Component + GetTypeRegistration + Reflect + Default + Serialize + DeserializeOwned {}
+pub trait ComponentReplicated: Component + GetTypeRegistration + TypePath + Reflect + Default + Serialize + DeserializeOwned {
+    /// Set with `#[component_replicated(id = ...)]`, needed when two type paths hash to the same id
+    /// or to keep the id of a component that was moved or renamed.
+    const REPLICATION_ID: Option<i32> = None;
+
+    /// The id sent on the wire for this component, `REPLICATION_ID` or a hash of the type path.
+    fn replication_id() -> i32 {
+        Self::REPLICATION_ID.unwrap_or_else(|| {
+            let hash = stable_hash(Self::type_path().as_bytes());
+
+            (hash ^ (hash >> 32)) as i32
+        })
+    }
+}
 
 pub trait RegisterReplicatedComponent{
     fn register_replicated_component<T: ComponentReplicated>(&mut self, network_side: &NetworkSide) -> &mut Self;
@@ -70,7 +89,7 @@ pub fn component_changed_server<T: ComponentReplicated>(
     mut commands: Commands
 ){
     let type_id = TypeId::of::<T>();
-    let id_registry = replication_components_registry.1.get(&type_id).unwrap();
+    let id_registry = replication_components_registry.0.get(&type_id).unwrap();
     let mut updated = false;
 
     for (entity, _, comp) in &added_query {
@@ -161,18 +180,25 @@ impl ReplicationComponentsRegistry {
 
         if self.is_registered(&type_id) { return; }
 
-        let new_id = self.0 + 1;
+        let new_id = T::replication_id();
+
+        if let Some(registered) = self.1.get(&new_id) {
+            panic!(
+                "Replicated components {} and {} both have the id {}, give one of them another id with #[component_replicated(id = ...)]",
+                registered.type_path, T::type_path(), new_id
+            );
+        }
 
-        self.0 = new_id;
-        self.1.insert(type_id, new_id);
-        self.2.insert(new_id, ReplicationInfo{
+        self.0.insert(type_id, new_id);
+        self.1.insert(new_id, ReplicationInfo{
             type_id,
+            type_path: T::type_path(),
             deserialize_fn: deserialize_component::<T>,
         });
     }
 
     pub fn is_registered(&self, type_id: &TypeId) -> bool {
-        self.1.contains_key(type_id)
+        self.0.contains_key(type_id)
     }
 }
 impl RegisterReplicatedComponent for App{
@@ -192,6 +218,8 @@ impl RegisterReplicatedComponent for App{
 
         replication_components_registry.registry::<T>();
 
+        self.world_mut().get_resource_or_init::<ProtocolSchema>().add_component(T::replication_id(), T::type_path());
+
         if network_side == &NetworkSide::Server {
             self.add_systems(Update,component_changed_server::<T>);
         }else if network_side == &NetworkSide::Client {
@@ -213,6 +241,9 @@ impl Plugin for ReplicatingPlugin {
             app.insert_resource(ServerReplicationQueue::default());
             app.insert_resource(NewClientsToReplicate::default());
 
+            app.world_mut().get_resource_or_init::<ProtocolSchema>().add_message::<ReplicateMessageFromServer>();
+
+            #[cfg(not(target_arch = "wasm32"))]
             app.add_systems(PostUpdate,replicate_to_client);
         }else if self.network_side == NetworkSide::Client {
             app.add_systems(Last,replication_from_server);
@@ -224,12 +255,14 @@ impl Plugin for ReplicatingPlugin {
 
             register_message_type::<ReplicateMessageFromServer>(app, &NetworkSide::Client);
 
+            #[cfg(not(target_arch = "wasm32"))]
             app.add_systems(PostUpdate,replicate_to_client);
             app.add_systems(Last,replication_from_server);
         }
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 pub fn replicate_to_client(
     replicate_query : Query<(Entity, &Replicated), With<Replicated>>,
     mut server_components_queue: ResMut<ServerReplicationQueue>,
@@ -280,7 +313,7 @@ pub fn replication_from_server(
         if let Some(entity) = have_entity {
             for (registry_id, bytes) in components_bytes {
                 let replication_infos = replication_components_registry
-                    .2
+                    .1
                     .get(registry_id)
                     .unwrap();
 
@@ -321,7 +354,7 @@ pub fn replication_from_server(
 
             for (registry_id, bytes) in components_bytes {
                 let replication_infos = replication_components_registry
-                    .2
+                    .1
                     .get(registry_id)
                     .unwrap();
 
@@ -355,4 +388,33 @@ pub fn replication_from_server(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component, Reflect, Default, Serialize, Deserialize)]
+    struct Health;
+
+    #[derive(Component, Reflect, Default, Serialize, Deserialize)]
+    struct Mana;
+
+    impl ComponentReplicated for Health {
+        const REPLICATION_ID: Option<i32> = Some(5);
+    }
+
+    impl ComponentReplicated for Mana {
+        const REPLICATION_ID: Option<i32> = Some(5);
+    }
+
+    #[test]
+    #[should_panic(expected = "both have the id 5, give one of them another id with #[component_replicated(id = ...)]")]
+    fn components_with_the_same_id_panic() {
+        let mut registry = ReplicationComponentsRegistry::default();
+
+        registry.registry::<Health>();
+        registry.registry::<Health>();
+        registry.registry::<Mana>();
+    }
+}
@@ -1,39 +1,53 @@
-﻿use std::any::Any;
+﻿use std::collections::HashMap;
 use std::sync::Arc;
 use bevy::app::App;
-use bevy::prelude::{Commands, First, IntoScheduleConfigs, Last, MessageWriter, Plugin, ResMut, Update, World};
-use uuid::Uuid;
-use crate::connections::{Connection, Connections, ConnectionsType, ServerConnectionType, ServerConnections};
-use crate::connections::tcp::connection::TcpConnection;
+use bevy::log::{info, warn};
+use bevy::platform::time::Instant;
+use bevy::prelude::{Commands, First, IntoScheduleConfigs, Last, MessageWriter, Plugin, Res, ResMut, Update};
+use crate::connections::ServerConnections;
+use crate::connections::auth::Authenticator;
+use crate::connections::heartbeat::{HeartbeatPing, HeartbeatPong, LinkHealth};
+use crate::connections::runtime::{init_network_runtime, NetworkRuntime};
+use crate::connections::session::SessionRequest;
+use crate::connections::transport::TransportState;
 use crate::NetworkSide;
-use crate::plugins::{ClientConnected, ClientDiconnected, ConnectedMessage};
+use crate::plugins::{AuthRequest, ClientConnected, ClientDiconnected, ClientReconnected, ConnectedMessage, DisconnectReason, ProtocolViolation};
 use crate::plugins::replication::{NewClientsToReplicate};
-use crate::systems::messaging::{register_message_type, DISPATCHERS};
+use crate::systems::batching::unbatch;
+use crate::systems::messaging::{queue_message_dispatch, register_message_type, MessageDispatchers};
+use crate::systems::protocol::ProtocolSchema;
 
 pub struct ServerPlugin;
 
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
         register_message_type::<ConnectedMessage>(app, &NetworkSide::Client);
+        init_network_runtime(app);
 
-        app.insert_resource(ServerConnections::new());
+        let routes = Arc::clone(&app.world().resource::<MessageDispatchers>().1);
+
+        app.insert_resource(ServerConnections(HashMap::new(), routes));
         app.add_message::<ClientConnected>();
         app.add_message::<ClientDiconnected>();
+        app.add_message::<ClientReconnected>();
+        app.add_message::<AuthRequest>();
+        app.add_message::<ProtocolViolation>();
         app.add_systems(First,(start_connections,check_client_connections_down).chain());
-        app.add_systems(Update,(check_clients_connected,check_clients_messages).chain());
-        app.add_systems(Last,(check_connection_up,start_listening_clients,restart_connection).chain());
+        app.add_systems(Update,(check_clients_connected,check_clients_messages,check_authentications,check_heartbeats).chain());
+        app.add_systems(Last,update_connections);
     }
 }
 
 pub fn start_connections(
     mut server_connections: ResMut<ServerConnections>,
+    network_runtime: Res<NetworkRuntime>,
 ){
-    for (_,connection) in server_connections.0.iter_mut() {
-        match connection {
-            ServerConnectionType::Tcp(connection) => {
-                connection.start_connection()
-            }
+    for connection in server_connections.0.values_mut() {
+        if connection.transport.state() == TransportState::Stopped {
+            connection.transport.set_runtime(network_runtime.handle().clone());
         }
+
+        connection.transport.start();
     }
 }
 
@@ -41,158 +55,572 @@ pub fn check_client_connections_down(
     mut server_connections: ResMut<ServerConnections>,
     mut client_diconnected: MessageWriter<ClientDiconnected>,
 ){
-    for (_,connection) in server_connections.0.iter_mut() {
-        match connection {
-            ServerConnectionType::Tcp(server_connection) => {
-                let mut remove_list: Vec<Uuid> = Vec::new();
+    for connection in server_connections.0.values_mut() {
+        for transport_id in connection.transport.poll_disconnected() {
+            connection.links.remove(&transport_id);
+            connection.closing.remove(&transport_id);
+            connection.pending_auth.remove(&transport_id);
+
+            if connection.sessions.session_of(&transport_id).is_none() {
+                connection.forget_counters(&transport_id);
+            }
+
+            if let Some(uuid) = connection.sessions.link_dropped(&transport_id) {
+                client_diconnected.write(ClientDiconnected(uuid, connection.transport.kind(), connection.name));
+                connection.forget_counters(&uuid);
+            }
+        }
 
-                for (uuid,client_connection) in server_connection.connections.iter_mut()  {
-                    match client_connection.connection_down_receiver.try_recv() {
-                        Ok(_) => {
-                            client_connection.listening = false;
+        for uuid in connection.sessions.expired() {
+            client_diconnected.write(ClientDiconnected(uuid, connection.transport.kind(), connection.name));
+            connection.forget_counters(&uuid);
+        }
 
-                            client_diconnected.write(ClientDiconnected(*uuid, ConnectionsType::Tcp, server_connection.name));
+        for uuid in std::mem::take(&mut connection.closed_sessions) {
+            client_diconnected.write(ClientDiconnected(uuid, connection.transport.kind(), connection.name));
+            connection.forget_counters(&uuid);
+        }
 
-                            remove_list.push(*uuid);
-                        }
-                        Err(_) => {
+        let now = Instant::now();
+        let closed: Vec<_> = connection.closing.iter()
+            .filter(|(_, close_at)| now >= **close_at)
+            .map(|(transport_id, _)| *transport_id)
+            .collect();
 
-                        }
-                    }
-                }
+        // Transports forget a link they were told to disconnect, so the session ends here
+        // instead of through `poll_disconnected`.
+        for transport_id in closed {
+            connection.closing.remove(&transport_id);
+            connection.pending_auth.remove(&transport_id);
+            connection.transport.disconnect_client(&transport_id);
 
-                for uuid in remove_list {
-                    server_connection.connections.remove(&uuid);
+            match connection.sessions.session_of(&transport_id) {
+                Some(uuid) => {
+                    connection.sessions.close(&uuid);
+                    connection.closed_sessions.push(uuid);
                 }
+                None => connection.forget_counters(&transport_id)
             }
         }
     }
 }
 
+/// New links only become clients once their `SessionRequest` arrives, see `check_clients_messages`.
 pub fn check_clients_connected(
     mut server_connections: ResMut<ServerConnections>,
-    mut client_connected_event: MessageWriter<ClientConnected>,
-    mut new_clients_to_replicate: Option<ResMut<NewClientsToReplicate>>,
 ){
-    for (_,connection) in server_connections.0.iter_mut() {
-        match connection {
-            ServerConnectionType::Tcp(connection) => {
-                match connection.client_connected_receiver.try_recv() {
-                    Ok((tcp_stream,_)) => {
-                        let settings = &connection.settings;
-                        let mut tcp_connection = TcpConnection::new(tcp_stream, connection.name, NetworkSide::Server, Arc::clone(&connection.cancel_token),settings.bytes,settings.order);
-                        let current_uuid = tcp_connection.uuid.unwrap();
-
-                        client_connected_event.write(ClientConnected(current_uuid,ConnectionsType::Tcp,connection.name));
-                        
-                        tcp_connection.send_message(&ConnectedMessage{
-                            uuid: current_uuid
-                        },connection.runtime.as_ref().unwrap());
-                        
-                        connection.connections.insert(current_uuid,tcp_connection);
-                        
-                        if let Some(new_clients_to_replicate) = new_clients_to_replicate.as_mut() {
-                            new_clients_to_replicate.0.push(current_uuid);
-                        }
-                    }
-                    Err(_) => {
-                        continue
-                    }
-                }
+    for connection in server_connections.0.values_mut() {
+        for transport_id in connection.transport.poll_accepted() {
+            if connection.is_banned(&transport_id) {
+                info!("Rejecting banned client {:?}", connection.transport.client_address(&transport_id));
+
+                connection.close_link(transport_id, DisconnectReason::Banned);
+                continue
             }
+
+            connection.links.insert(transport_id, LinkHealth::new());
         }
     }
 }
 
 pub fn check_clients_messages(
     mut server_connections: ResMut<ServerConnections>,
+    protocol_schema: Res<ProtocolSchema>,
+    mut auth_request: MessageWriter<AuthRequest>,
+    mut protocol_violation: MessageWriter<ProtocolViolation>,
     mut commands: Commands,
 ){
-    for (_,connection) in server_connections.0.iter_mut() {
-        match connection {
-            ServerConnectionType::Tcp(connection) => {
-                for (uuid,client_connection) in connection.connections.iter_mut()  {
-                    match client_connection.message_received_receiver.try_recv() {
-                        Ok(message) => {
-                            let connection_name = connection.name;
-                            let uuid = *uuid;
-
-                            commands.queue(move |w: &mut World| {
-                                let type_id = message.as_any().type_id();
-                                let map = DISPATCHERS.lock().unwrap();
-                                if let Some(dispatcher) = map.get(&type_id) {
-                                    let boxed_any = message as Box<dyn Any>;
-
-                                    dispatcher(boxed_any, w, ConnectionsType::Tcp, Some(uuid), &NetworkSide::Server, connection_name);
-                                } else {
-                                    println!("This message does not exist");
-                                }
-                            });
-                        }
-                        Err(_) => {
-
-                        }
-                    }
+    let protocol = protocol_schema.info();
+
+    for connection in server_connections.0.values_mut() {
+        for transport_id in connection.transport.poll_rejected() {
+            let sender = connection.sessions.session_of(&transport_id).unwrap_or(transport_id);
+
+            *connection.rejected_frames.entry(sender).or_default() += 1;
+            connection.close_link(transport_id, DisconnectReason::ProtocolViolation);
+        }
+
+        for (transport_id, message) in connection.transport.poll_received().into_iter()
+            .flat_map(|(transport_id, message)| unbatch(message).into_iter().map(move |message| (transport_id, message))) {
+            let mut link = connection.links.get_mut(&transport_id);
+
+            if let Some(link) = link.as_mut() {
+                link.received();
+            }
+
+            if !connection.routes.read().unwrap().direction(message.as_ref()).from_client() {
+                let sender = connection.sessions.session_of(&transport_id).unwrap_or(transport_id);
+                let violations = connection.protocol_violations.entry(sender).or_default();
+
+                *violations += 1;
+                warn!("Client {} sent a message only the server can send", sender);
+
+                protocol_violation.write(ProtocolViolation(connection.name, sender, *violations));
+                continue
+            }
+
+            if let Some(ping) = message.as_any().downcast_ref::<HeartbeatPing>() {
+                connection.transmit(&transport_id, &HeartbeatPong{
+                    sent_at: ping.sent_at
+                });
+
+                continue
+            }
+
+            if let Some(pong) = message.as_any().downcast_ref::<HeartbeatPong>() {
+                if let Some(link) = link {
+                    link.pong_received(pong);
+                }
+
+                continue
+            }
+
+            if let Some(session_request) = message.as_any().downcast_ref::<SessionRequest>() {
+                if connection.sessions.session_of(&transport_id).is_some()
+                    || connection.closing.contains_key(&transport_id)
+                    || connection.pending_auth.contains_key(&transport_id) {
+                    continue
                 }
+
+                if session_request.protocol != protocol {
+                    warn!("Client protocol {:?} does not match {:?}", session_request.protocol, protocol);
+
+                    connection.close_link(transport_id, DisconnectReason::VersionMismatch);
+                    continue
+                }
+
+                if let Some(resume) = session_request.resume
+                    && connection.bans.clients.contains(&resume.uuid) {
+                    connection.close_link(transport_id, DisconnectReason::Banned);
+                    continue
+                }
+
+                let address = connection.transport.client_address(&transport_id);
+
+                if let Authenticator::Manual = connection.authenticator {
+                    auth_request.write(AuthRequest {
+                        connection_name: connection.name,
+                        request: transport_id,
+                        credentials: session_request.credentials.clone(),
+                        address
+                    });
+                }
+
+                let pending_auth = connection.authenticator.start(session_request.resume, session_request.credentials.clone(), address);
+
+                connection.pending_auth.insert(transport_id, pending_auth);
+
+                continue
             }
+
+            let Some(uuid) = connection.sessions.session_of(&transport_id) else {
+                continue
+            };
+
+            queue_message_dispatch(&mut commands, message, connection.transport.kind(), Some(uuid), NetworkSide::Server, connection.name);
         }
     }
 }
 
-pub fn check_connection_up(
+/// Admits the links the authenticator accepted, only then they get a uuid and are replicated.
+pub fn check_authentications(
     mut server_connections: ResMut<ServerConnections>,
+    mut client_connected_event: MessageWriter<ClientConnected>,
+    mut client_reconnected_event: MessageWriter<ClientReconnected>,
+    mut new_clients_to_replicate: Option<ResMut<NewClientsToReplicate>>,
 ){
-    for (_,connection) in server_connections.0.iter_mut() {
-        match connection {
-            ServerConnectionType::Tcp(connection) => {
-                match connection.connection_up_receiver.try_recv() {
-                    Ok(tcp_listener) => {
-                        if let Some(listener) = connection.listener.take() {
-                            drop(listener);
-                        }
-
-                        connection.listener = Some(tcp_listener);
-                    }
-                    Err(_) => {
-                        continue
-                    }
-                }
+    for connection in server_connections.0.values_mut() {
+        let decided: Vec<_> = connection.pending_auth.iter_mut()
+            .filter_map(|(transport_id, pending_auth)| pending_auth.poll().map(|accepted| (*transport_id, accepted)))
+            .collect();
+
+        for (transport_id, accepted) in decided {
+            let Some(pending_auth) = connection.pending_auth.remove(&transport_id) else {
+                continue
+            };
+
+            if !accepted {
+                info!("Client {:?} was not authorized", connection.transport.client_address(&transport_id));
+
+                connection.close_link(transport_id, DisconnectReason::Unauthorized);
+                continue
+            }
+
+            let opened = connection.sessions.open(transport_id, pending_auth.resume);
+
+            if let Some(replaced) = opened.replaced {
+                connection.links.remove(&replaced);
+                connection.transport.disconnect_client(&replaced);
+            }
+
+            connection.transmit(&transport_id, &ConnectedMessage{
+                uuid: opened.uuid,
+                resume_token: opened.token,
+                resumed: opened.resumed
+            });
+
+            if opened.resumed {
+                client_reconnected_event.write(ClientReconnected(opened.uuid, connection.transport.kind(), connection.name));
+            } else {
+                client_connected_event.write(ClientConnected(opened.uuid, connection.transport.kind(), connection.name));
+            }
+
+            if let Some(new_clients_to_replicate) = new_clients_to_replicate.as_mut() {
+                new_clients_to_replicate.0.push(opened.uuid);
             }
         }
     }
 }
 
-pub fn start_listening_clients(
+pub fn check_heartbeats(
     mut server_connections: ResMut<ServerConnections>,
+    mut client_diconnected: MessageWriter<ClientDiconnected>,
 ){
-    for (_,connection) in server_connections.0.iter_mut() {
-        match connection {
-            ServerConnectionType::Tcp(connection) => {
-                for (_,client_connection) in connection.connections.iter_mut()  {
-                    if client_connection.listening {continue}
+    for connection in server_connections.0.values_mut() {
+        let mut timed_out = Vec::new();
+        let mut pings = Vec::new();
 
-                    client_connection.start_listening(&connection.runtime.as_ref().unwrap());
-                }
+        for (transport_id, link) in connection.links.iter_mut() {
+            if link.timed_out(&connection.heartbeat) {
+                timed_out.push(*transport_id);
+                continue
+            }
+
+            if let Some(ping) = link.ping(&connection.heartbeat) {
+                pings.push((*transport_id, ping));
+            }
+        }
+
+        for (transport_id, ping) in pings {
+            connection.transmit(&transport_id, &ping);
+        }
+
+        for transport_id in timed_out {
+            info!("Client {} timed out", connection.sessions.session_of(&transport_id).unwrap_or(transport_id));
+
+            connection.links.remove(&transport_id);
+            connection.pending_auth.remove(&transport_id);
+            connection.transport.disconnect_client(&transport_id);
+
+            if let Some(uuid) = connection.sessions.link_dropped(&transport_id) {
+                client_diconnected.write(ClientDiconnected(uuid, connection.transport.kind(), connection.name));
+                connection.forget_counters(&uuid);
             }
         }
     }
 }
 
-pub fn restart_connection(
+pub fn update_connections(
     mut server_connections: ResMut<ServerConnections>,
 ){
-    for (_,connection) in server_connections.0.iter_mut() {
-        match connection {
-            ServerConnectionType::Tcp(connection) => {
-                match connection.connection_down_receiver.try_recv() {
-                    Ok(_) => {
-                        connection.cancel_connection()
-                    }
-                    Err(_) => {
-                        continue
-                    }
-                }
+    for connection in server_connections.0.values_mut() {
+        connection.flush();
+        connection.transport.update();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+    use std::time::Duration;
+    use bevy::prelude::{MessageReader, Resource};
+    use uuid::Uuid;
+    use super::*;
+    use crate::connections::{BytesOptions, ClientConnectionState, ClientConnections, OrderOptions};
+    use crate::connections::heartbeat::HeartbeatSettings;
+    use crate::connections::tcp::server::{ServerTcpConnection, ServerTcpSettings};
+    use crate::plugins::DisconnectedFromServer;
+    use crate::plugins::testing::LocalPair;
+    use message_derive::Message;
+    use serde::{Deserialize, Serialize};
+    use crate::systems::messaging::{encode_message, register_message_type_with_direction, MessageDirection, MessageReceivedFromClient, MessageTrait};
+
+    #[derive(Serialize, Deserialize, Message, Clone)]
+    #[message(direction = ServerToClient)]
+    struct ServerOnly;
+
+    #[derive(Serialize, Deserialize, Message, Clone)]
+    struct Anyone;
+
+    #[derive(Resource, Default)]
+    struct Received(usize, usize);
+
+    fn record_received(mut server_only: MessageReader<MessageReceivedFromClient<ServerOnly>>, mut anyone: MessageReader<MessageReceivedFromClient<Anyone>>, mut received: ResMut<Received>) {
+        received.0 += server_only.read().count();
+        received.1 += anyone.read().count();
+    }
+
+    #[derive(Resource, Default)]
+    struct Disconnected(Vec<Uuid>);
+
+    fn record_disconnects(mut client_diconnected: MessageReader<ClientDiconnected>, mut disconnected: ResMut<Disconnected>) {
+        disconnected.0.extend(client_diconnected.read().map(|client| client.0));
+    }
+
+    #[derive(Resource, Default)]
+    struct AuthRequests(Vec<(Uuid, Vec<u8>)>);
+
+    fn record_auth_requests(mut auth_request: MessageReader<AuthRequest>, mut requests: ResMut<AuthRequests>) {
+        requests.0.extend(auth_request.read().map(|request| (request.request, request.credentials.clone())));
+    }
+
+    #[derive(Resource, Default)]
+    struct Reasons(Vec<DisconnectReason>);
+
+    fn record_reasons(mut disconnected_from_server: MessageReader<DisconnectedFromServer>, mut reasons: ResMut<Reasons>) {
+        reasons.0.extend(disconnected_from_server.read().map(|disconnected| disconnected.1.clone()));
+    }
+
+    /// A pair with a manual authenticator, returns the request of the client.
+    fn manual_auth(credentials: &[u8]) -> (LocalPair, Uuid) {
+        let mut pair = LocalPair::new();
+
+        pair.server.init_resource::<AuthRequests>();
+        pair.server.add_systems(Last, record_auth_requests);
+        pair.client.init_resource::<Reasons>();
+        pair.client.add_systems(Last, record_reasons);
+        pair.server_connections().set_authenticator("memory", Authenticator::Manual);
+        pair.client_connections().set_credentials("memory", credentials.to_vec());
+
+        assert!(pair.update_until(|pair| !pair.server.world().resource::<AuthRequests>().0.is_empty()));
+
+        let (request, received) = pair.server.world().resource::<AuthRequests>().0[0].clone();
+
+        assert_eq!(received, credentials);
+
+        (pair, request)
+    }
+
+    #[test]
+    fn accepted_client_gets_a_uuid() {
+        let (mut pair, request) = manual_auth(b"secret");
+
+        for _ in 0..5 {
+            pair.update();
+        }
+
+        assert_eq!(pair.client_connection().connection_state(), ClientConnectionState::Handshaking);
+
+        pair.server_connections().accept_client("memory", &request);
+
+        assert!(pair.connected());
+    }
+
+    #[test]
+    fn rejected_client_is_told_why() {
+        let (mut pair, request) = manual_auth(b"wrong");
+
+        pair.server_connections().reject_client("memory", &request);
+
+        assert!(pair.update_until(|pair| !pair.client.world().resource::<Reasons>().0.is_empty()));
+        assert_eq!(pair.client.world().resource::<Reasons>().0, vec![DisconnectReason::Unauthorized]);
+        assert!(pair.client.world().resource::<ClientConnections>().0["memory"].gave_up());
+        assert!(pair.server_connection().clients().is_empty());
+    }
+
+    /// Updates until the client was told why it was disconnected, the server must not admit it.
+    fn refused_with(mut pair: LocalPair) -> Vec<DisconnectReason> {
+        pair.client.init_resource::<Reasons>();
+        pair.client.add_systems(Last, record_reasons);
+
+        assert!(pair.update_until(|pair| !pair.client.world().resource::<Reasons>().0.is_empty()));
+        assert!(pair.server_connection().clients().is_empty());
+        assert!(pair.client.world().resource::<ClientConnections>().0["memory"].gave_up());
+
+        pair.client.world().resource::<Reasons>().0.clone()
+    }
+
+    #[test]
+    fn other_app_version_is_refused() {
+        let mut pair = LocalPair::new();
+
+        pair.server.world_mut().resource_mut::<ProtocolSchema>().set_app_version(2);
+
+        assert_eq!(refused_with(pair), vec![DisconnectReason::VersionMismatch]);
+    }
+
+    #[test]
+    fn other_schema_is_refused() {
+        let mut pair = LocalPair::new();
+
+        register_message_type::<Anyone>(&mut pair.server, &NetworkSide::Server);
+
+        assert_eq!(refused_with(pair), vec![DisconnectReason::VersionMismatch]);
+    }
+
+    #[test]
+    fn messages_sent_the_wrong_way_are_counted_and_dropped() {
+        let mut pair = LocalPair::new();
+
+        for app in [&mut pair.server, &mut pair.client] {
+            register_message_type::<ServerOnly>(app, &NetworkSide::Server);
+            register_message_type::<Anyone>(app, &NetworkSide::Server);
+        }
+
+        pair.server.init_resource::<Received>();
+        pair.server.add_systems(Last, record_received);
+
+        assert!(pair.connected());
+
+        let uuid = pair.client_connection().uuid().unwrap();
+
+        pair.client_connections().send_message("memory", &ServerOnly);
+        pair.client_connections().send_message("memory", &Anyone);
+
+        assert!(pair.update_until(|pair| pair.server.world().resource::<Received>().1 == 1));
+        assert_eq!(pair.server.world().resource::<Received>().0, 0);
+        assert_eq!(pair.server_connection().protocol_violations(&uuid), 1);
+    }
+
+    #[test]
+    fn registered_directions_override_the_message() {
+        let mut pair = LocalPair::new();
+
+        for app in [&mut pair.server, &mut pair.client] {
+            register_message_type::<ServerOnly>(app, &NetworkSide::Server);
+        }
+
+        register_message_type_with_direction::<Anyone>(&mut pair.server, &NetworkSide::Server, MessageDirection::ServerToClient);
+        register_message_type::<Anyone>(&mut pair.client, &NetworkSide::Server);
+
+        pair.server.init_resource::<Received>();
+        pair.server.add_systems(Last, record_received);
+
+        assert!(pair.connected());
+
+        let uuid = pair.client_connection().uuid().unwrap();
+
+        pair.client_connections().send_message("memory", &Anyone);
+
+        assert!(pair.update_until(|pair| pair.server_connection().protocol_violations(&uuid) == 1));
+        assert_eq!(pair.server.world().resource::<Received>().1, 0);
+    }
+
+    #[test]
+    fn silent_client_times_out() {
+        let mut pair = LocalPair::new();
+
+        pair.server.init_resource::<Disconnected>();
+        pair.server.add_systems(Last, record_disconnects);
+        // The client stays silent, so only the timeout can end the link.
+        pair.client_connections().set_heartbeat("memory", HeartbeatSettings::new(Duration::from_secs(3600), Duration::from_secs(3600)));
+
+        assert!(pair.connected());
+
+        let uuid = pair.client_connection().uuid().unwrap();
+        let started_at = Instant::now() - Duration::from_secs(11);
+
+        for link in pair.server_connections().0.get_mut("memory").unwrap().links.values_mut() {
+            *link = LinkHealth::started_at(started_at);
+        }
+
+        pair.server.update();
+
+        assert_eq!(pair.server.world().resource::<Disconnected>().0, vec![uuid]);
+        assert!(pair.server_connection().clients().is_empty());
+    }
+
+    fn write_frame(stream: &mut TcpStream, message: &dyn MessageTrait) {
+        let encoded = encode_message(message).unwrap();
+
+        stream.write_all(&(encoded.len() as u32 + 1).to_le_bytes()).unwrap();
+        stream.write_all(&[0]).unwrap();
+        stream.write_all(&encoded).unwrap();
+    }
+
+    fn connection(app: &App) -> &crate::connections::ServerConnection {
+        app.world().resource::<ServerConnections>().0.get("tcp").unwrap()
+    }
+
+    fn update_until(app: &mut App, mut done: impl FnMut(&mut App) -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while Instant::now() < deadline {
+            app.update();
+
+            if done(app) {
+                return true;
             }
+
+            std::thread::sleep(Duration::from_millis(10));
         }
+
+        false
+    }
+
+    #[test]
+    fn oversized_frame_ends_the_session() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut app = App::new();
+
+        app.add_plugins(ServerPlugin);
+        app.init_resource::<Disconnected>();
+        app.add_systems(Last, record_disconnects);
+        app.world_mut().resource_mut::<ServerConnections>().new_server_tcp_connection(
+            ServerTcpSettings::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port, BytesOptions::U32, OrderOptions::LittleEndian, 0, false),
+            "tcp"
+        );
+
+        let mut stream = None;
+
+        assert!(update_until(&mut app, |_| {
+            stream = TcpStream::connect(("127.0.0.1", port)).ok();
+            stream.is_some()
+        }));
+
+        let mut stream = stream.unwrap();
+        let protocol = app.world().resource::<ProtocolSchema>().info();
+
+        write_frame(&mut stream, &SessionRequest {
+            resume: None,
+            credentials: Vec::new(),
+            protocol
+        });
+
+        assert!(update_until(&mut app, |app| !connection(app).clients().is_empty()));
+
+        let uuid = connection(&app).clients()[0];
+
+        stream.write_all(&u32::MAX.to_le_bytes()).unwrap();
+
+        assert!(update_until(&mut app, |app| connection(app).rejected_frames(&uuid) == 1));
+        assert!(update_until(&mut app, |app| app.world().resource::<Disconnected>().0.contains(&uuid)));
+        assert!(connection(&app).clients().is_empty());
+        assert_eq!(connection(&app).rejected_frames(&uuid), 0);
+    }
+
+    fn runtime_thread(app: &mut App) -> String {
+        let mut server_connections = app.world_mut().resource_mut::<ServerConnections>();
+        let transport = &mut server_connections.0.get_mut("tcp").unwrap().transport;
+        let transport = (transport.as_mut() as &mut dyn std::any::Any).downcast_mut::<ServerTcpConnection>().unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        transport.runtime.as_ref().unwrap().spawn(async move {
+            let _ = sender.send(std::thread::current().name().unwrap_or_default().to_string());
+        });
+
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn each_app_starts_its_connections_on_its_own_runtime() {
+        let runtimes = ["lobby-runtime", "game-runtime"].map(|name| {
+            tokio::runtime::Builder::new_multi_thread().worker_threads(1).thread_name(name).enable_all().build().unwrap()
+        });
+        let mut apps = runtimes.each_ref().map(|runtime| {
+            let mut app = App::new();
+
+            app.insert_resource(NetworkRuntime::from_handle(runtime.handle().clone()));
+            app.add_plugins(ServerPlugin);
+            app.world_mut().resource_mut::<ServerConnections>()
+                .new_server_tcp_connection(ServerTcpSettings::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0, BytesOptions::U32, OrderOptions::LittleEndian, 0, false), "tcp");
+            app.update();
+            app
+        });
+
+        assert_eq!(runtime_thread(&mut apps[0]), "lobby-runtime");
+        assert_eq!(runtime_thread(&mut apps[1]), "game-runtime");
+    }
+}
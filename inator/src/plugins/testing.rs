@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+use bevy::app::App;
+use bevy::prelude::Mut;
+use crate::connections::{ClientConnection, ClientConnectionState, ClientConnections, ServerConnection, ServerConnections};
+use crate::connections::memory::client::ClientMemorySettings;
+use crate::connections::memory::server::ServerMemorySettings;
+use crate::plugins::client::ClientPlugin;
+use crate::plugins::server::ServerPlugin;
+
+/// A server App and a client App linked by a memory connection named "memory".
+pub(crate) struct LocalPair {
+    pub(crate) server: App,
+    pub(crate) client: App
+}
+
+impl LocalPair {
+    pub(crate) fn new() -> Self {
+        let mut server = App::new();
+        let mut client = App::new();
+
+        server.add_plugins(ServerPlugin);
+        client.add_plugins(ClientPlugin);
+
+        let mut server_connections = server.world_mut().resource_mut::<ServerConnections>();
+
+        server_connections.new_server_memory_connection(ServerMemorySettings::new(0), "memory");
+
+        let connector = server_connections.memory_connector("memory").unwrap();
+
+        client.world_mut().resource_mut::<ClientConnections>()
+            .new_client_memory_connection(ClientMemorySettings::new(connector), "memory");
+
+        LocalPair {
+            server,
+            client
+        }
+    }
+
+    pub(crate) fn update(&mut self) {
+        self.client.update();
+        self.server.update();
+    }
+
+    /// Updates both Apps until `done`, false if it took more than 5 seconds.
+    pub(crate) fn update_until(&mut self, done: impl FnMut(&mut LocalPair) -> bool) -> bool {
+        self.update_within(Duration::from_secs(5), done)
+    }
+
+    pub(crate) fn update_within(&mut self, timeout: Duration, mut done: impl FnMut(&mut LocalPair) -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        while Instant::now() < deadline {
+            self.update();
+
+            if done(self) {
+                return true;
+            }
+
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        false
+    }
+
+    pub(crate) fn connected(&mut self) -> bool {
+        self.update_until(|pair| pair.client_connection().connection_state() == ClientConnectionState::Connected)
+    }
+
+    pub(crate) fn server_connection(&self) -> &ServerConnection {
+        self.server.world().resource::<ServerConnections>().0.get("memory").unwrap()
+    }
+
+    pub(crate) fn client_connection(&self) -> &ClientConnection {
+        self.client.world().resource::<ClientConnections>().0.get("memory").unwrap()
+    }
+
+    pub(crate) fn client_connections(&mut self) -> Mut<'_, ClientConnections> {
+        self.client.world_mut().resource_mut::<ClientConnections>()
+    }
+
+    pub(crate) fn server_connections(&mut self) -> Mut<'_, ServerConnections> {
+        self.server.world_mut().resource_mut::<ServerConnections>()
+    }
+}
@@ -0,0 +1,131 @@
+use std::any::Any;
+use serde::{Deserialize, Serialize};
+use crate::connections::MessageChannel;
+use crate::systems::messaging::{deserialize_message, encode_message, MessageRoutes, MessageTrait};
+
+/// Largest batch built from queued messages by default, small enough to fit in one datagram on
+/// most links. A single message bigger than this is still sent, just on its own.
+pub const DEFAULT_BATCH_SIZE: usize = 1200;
+
+/// Messages queued for the same peer during a frame, each one already encoded.
+/// Batches are sent on the channel shared by their messages.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct MessageBatch {
+    pub messages: Vec<Vec<u8>>,
+    #[serde(skip)]
+    pub channel: MessageChannel
+}
+
+impl MessageTrait for MessageBatch {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn clone_message(&self) -> Box<dyn MessageTrait> {
+        Box::new(self.clone())
+    }
+
+    fn channel(&self) -> MessageChannel {
+        self.channel
+    }
+}
+
+/// Packs consecutive messages on the same channel into batches of at most `batch_size` bytes,
+/// so the order they were queued in is kept. Batches of one message are sent as that message,
+/// and messages that can not be encoded are passed on alone for the transport to report.
+pub(crate) fn batch_messages(messages: Vec<Box<dyn MessageTrait>>, batch_size: usize, routes: &MessageRoutes) -> Vec<Box<dyn MessageTrait>> {
+    let mut batched: Vec<Box<dyn MessageTrait>> = Vec::new();
+    let mut pending: Vec<(Box<dyn MessageTrait>, Vec<u8>)> = Vec::new();
+    let mut pending_size = 0;
+    let mut pending_channel = MessageChannel::default();
+
+    for message in messages {
+        let Some(encoded) = encode_message(message.as_ref()) else {
+            if !pending.is_empty() {
+                batched.push(close_batch(std::mem::take(&mut pending), pending_channel));
+                pending_size = 0;
+            }
+
+            batched.push(message);
+            continue
+        };
+
+        let channel = routes.channel(message.as_ref());
+
+        if !pending.is_empty() && (channel != pending_channel || pending_size + encoded.len() > batch_size) {
+            batched.push(close_batch(std::mem::take(&mut pending), pending_channel));
+            pending_size = 0;
+        }
+
+        pending_channel = channel;
+        pending_size += encoded.len();
+        pending.push((message, encoded));
+    }
+
+    if !pending.is_empty() {
+        batched.push(close_batch(pending, pending_channel));
+    }
+
+    batched
+}
+
+fn close_batch(mut pending: Vec<(Box<dyn MessageTrait>, Vec<u8>)>, channel: MessageChannel) -> Box<dyn MessageTrait> {
+    if pending.len() == 1 {
+        return pending.remove(0).0;
+    }
+
+    Box::new(MessageBatch {
+        messages: pending.into_iter().map(|(_, encoded)| encoded).collect(),
+        channel
+    })
+}
+
+/// The messages of a received batch, or the message itself when it is not one.
+pub(crate) fn unbatch(message: Box<dyn MessageTrait>) -> Vec<Box<dyn MessageTrait>> {
+    match message.as_any().downcast_ref::<MessageBatch>() {
+        Some(batch) => batch.messages.iter().filter_map(|encoded| deserialize_message(encoded)).collect(),
+        None => vec![message]
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use message_derive::Message;
+    use super::*;
+    use crate::connections::heartbeat::HeartbeatPing;
+
+    #[derive(Serialize, Deserialize, Message, Clone)]
+    #[message(channel = Unreliable)]
+    struct Unregistered;
+
+    fn ping(sent_at: u64) -> Box<dyn MessageTrait> {
+        Box::new(HeartbeatPing { sent_at })
+    }
+
+    fn sent_at(message: &dyn MessageTrait) -> Option<u64> {
+        message.as_any().downcast_ref::<HeartbeatPing>().map(|ping| ping.sent_at)
+    }
+
+    #[test]
+    fn batch_round_trips_through_unbatch() {
+        let batched = batch_messages(vec![ping(1), ping(2), ping(3)], DEFAULT_BATCH_SIZE, &MessageRoutes::default());
+
+        assert_eq!(batched.len(), 1);
+
+        let received = deserialize_message(&encode_message(batched[0].as_ref()).unwrap()).unwrap();
+        let messages = unbatch(received);
+
+        assert_eq!(messages.iter().map(|message| sent_at(message.as_ref())).collect::<Vec<_>>(), vec![Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn unencodable_message_is_passed_on_in_order() {
+        let batched = batch_messages(vec![ping(1), ping(2), Box::new(Unregistered), ping(3)], DEFAULT_BATCH_SIZE, &MessageRoutes::default());
+
+        assert_eq!(batched.len(), 3);
+        assert!(batched[0].as_any().is::<MessageBatch>());
+        assert!(batched[1].as_any().is::<Unregistered>());
+        assert_eq!(sent_at(batched[2].as_ref()), Some(3));
+    }
+}
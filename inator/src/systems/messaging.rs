@@ -1,20 +1,61 @@
-﻿use std::any::{Any, TypeId};
+﻿use std::any::{type_name, Any, TypeId};
 use std::collections::HashMap;
-use std::io::Cursor;
-use std::sync::Mutex;
+use std::sync::{Arc, LazyLock, RwLock};
 use bevy::app::App;
-use bevy::prelude::{Message, World};
+use bevy::prelude::{Commands, Message, Resource, World};
 use bincode::config::standard;
-use typetag::__private21::once_cell::sync::Lazy;
+use bincode::error::EncodeError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use uuid::Uuid;
-use crate::connections::ConnectionsType;
+use crate::connections::{ConnectionsType, MessageChannel};
+use crate::connections::heartbeat::{HeartbeatPing, HeartbeatPong};
+use crate::connections::session::SessionRequest;
 use crate::NetworkSide;
+use crate::plugins::{ConnectedMessage, DisconnectMessage};
+use crate::plugins::replication::ReplicateMessageFromServer;
+use crate::systems::batching::MessageBatch;
+use crate::systems::protocol::{stable_hash, wire_name, ProtocolSchema};
 
 pub struct MessagingPlugin;
 
-#[typetag::serde]
+/// Who may send a message type. Frames coming the wrong way are dropped and counted as
+/// protocol violations against the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageDirection {
+    ServerToClient,
+    ClientToServer,
+    Both
+}
+
+impl MessageDirection {
+    pub fn from_server(self) -> bool {
+        self != MessageDirection::ClientToServer
+    }
+
+    pub fn from_client(self) -> bool {
+        self != MessageDirection::ServerToClient
+    }
+}
+
 pub trait MessageTrait: Send + Sync + Any {
     fn as_any(&self) -> &dyn Any;
+
+    fn clone_message(&self) -> Box<dyn MessageTrait>;
+
+    fn channel(&self) -> MessageChannel {
+        MessageChannel::ReliableOrdered
+    }
+
+    fn direction(&self) -> MessageDirection {
+        MessageDirection::Both
+    }
+
+    /// Set with `#[message(id = ...)]`, needed when two type names hash to the same id
+    /// or to keep the id of a message that was renamed.
+    fn message_id() -> Option<u32> where Self: Sized {
+        None
+    }
 }
 
 #[derive(Message)]
@@ -34,8 +75,98 @@ pub struct MessageReceivedFromClient<T: MessageTrait>{
 
 pub type DispatcherFn = Box<dyn Fn(Box<dyn Any>, &mut World, ConnectionsType, Option<Uuid>, &NetworkSide, &'static str) + Send + Sync>;
 
-pub static DISPATCHERS: Lazy<Mutex<HashMap<TypeId, DispatcherFn>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+/// Turns received messages into `MessageReceivedFromServer`/`MessageReceivedFromClient`, one table
+/// per App so several Apps in the same process only see the message types they registered.
+/// The routes are shared with the connections of the same App.
+#[derive(Resource, Default)]
+pub struct MessageDispatchers(pub(crate) HashMap<TypeId, DispatcherFn>, pub(crate) SharedMessageRoutes);
+
+/// Channels and directions set with `register_message_type_with_channel` and
+/// `register_message_type_with_direction`, other types keep the ones of `MessageTrait`.
+#[derive(Default)]
+pub(crate) struct MessageRoutes {
+    channels: HashMap<TypeId, MessageChannel>,
+    directions: HashMap<TypeId, MessageDirection>
+}
+
+pub(crate) type SharedMessageRoutes = Arc<RwLock<MessageRoutes>>;
+
+type EncodeFn = fn(&dyn MessageTrait, &mut Vec<u8>) -> Result<usize, EncodeError>;
+type DecodeFn = fn(&[u8]) -> Option<Box<dyn MessageTrait>>;
+
+struct MessageCodec {
+    type_path: &'static str,
+    decode: DecodeFn
+}
+
+/// Frames are the message id followed by the bincode payload. The crate's own messages are
+/// always registered, so the handshake works before the schemas are compared.
+#[derive(Default)]
+pub(crate) struct MessageCodecs {
+    ids: HashMap<TypeId, (u32, EncodeFn)>,
+    codecs: HashMap<u32, MessageCodec>
+}
+
+pub(crate) static MESSAGE_CODECS: LazyLock<RwLock<MessageCodecs>> = LazyLock::new(|| {
+    let mut codecs = MessageCodecs::default();
+
+    codecs.register::<SessionRequest>();
+    codecs.register::<ConnectedMessage>();
+    codecs.register::<DisconnectMessage>();
+    codecs.register::<HeartbeatPing>();
+    codecs.register::<HeartbeatPong>();
+    codecs.register::<ReplicateMessageFromServer>();
+    codecs.register::<MessageBatch>();
+
+    RwLock::new(codecs)
+});
+
+/// The id sent on the wire for this message type, `MessageTrait::message_id` or a hash of its
+/// full type path, so both sides have to declare it in the same crate and module.
+/// Two registered types with the same id collide, see `MessageCodecs::register`.
+pub fn message_wire_id<T: MessageTrait>() -> u32 {
+    T::message_id().unwrap_or_else(|| {
+        let hash = stable_hash(type_name::<T>().as_bytes());
+
+        (hash ^ (hash >> 32)) as u32
+    })
+}
+
+fn encode_payload<T: MessageTrait + Serialize>(message: &dyn MessageTrait, buf: &mut Vec<u8>) -> Result<usize, EncodeError> {
+    let message = message.as_any().downcast_ref::<T>().expect("Failed to downcast");
+
+    bincode::serde::encode_into_std_write(message, buf, standard())
+}
+
+fn decode_payload<T: MessageTrait + DeserializeOwned>(buf: &[u8]) -> Option<Box<dyn MessageTrait>> {
+    match bincode::serde::decode_from_slice::<T, _>(buf, standard()) {
+        Ok((message, _)) => Some(Box::new(message)),
+        Err(e) => {println!("Decode error {} ", e); None},
+    }
+}
+
+impl MessageCodecs {
+    pub(crate) fn register<T: MessageTrait + Serialize + DeserializeOwned>(&mut self) {
+        let type_id = TypeId::of::<T>();
+
+        if self.ids.contains_key(&type_id) { return; }
+
+        let id = message_wire_id::<T>();
+
+        if let Some(registered) = self.codecs.get(&id) {
+            panic!(
+                "Message types {} and {} both have the id {}. Give one of them another id with #[message(id = ...)]",
+                registered.type_path, type_name::<T>(), id
+            );
+        }
+
+        self.ids.insert(type_id, (id, encode_payload::<T>));
+        self.codecs.insert(id, MessageCodec {
+            type_path: type_name::<T>(),
+            decode: decode_payload::<T>
+        });
+    }
+}
 
 #[macro_export]
 macro_rules! register_message_type {
@@ -63,26 +194,64 @@ macro_rules! register_message_type {
             },
         );
 
-        let mut map = $dispatcher_map.lock().unwrap();
-        let type_id = TypeId::of::<$type>();
+        // The dispatcher does not depend on the side, registering a type again only adds its messages.
+        $dispatcher_map.entry(TypeId::of::<$type>()).or_insert(dispatcher);
+    }};
+}
+
+pub fn encode_message(message: &dyn MessageTrait) -> Option<Vec<u8>> {
+    let config = standard();
+    let codecs = MESSAGE_CODECS.read().unwrap();
+
+    let Some((id, encode)) = codecs.ids.get(&message.as_any().type_id()) else {
+        eprintln!("Failed to encode message: message type not registered");
+        return None;
+    };
+
+    let mut encoded = bincode::encode_to_vec(id, config).ok()?;
 
-        if !map.contains_key(&type_id) {
-           map.insert(type_id, dispatcher);
+    match encode(message, &mut encoded) {
+        Ok(_) => Some(encoded),
+        Err(e) => {
+            eprintln!("Failed to encode message: {:?}", e);
+            None
         }
-    }};
+    }
 }
 
 pub fn deserialize_message(buf: &[u8]) -> Option<Box<dyn MessageTrait>> {
     let config = standard();
-    let mut cursor = Cursor::new(buf);
 
-    match bincode::serde::decode_from_std_read::<Box<dyn MessageTrait>, _, _>(&mut cursor, config) {
-        Ok(msg) => Some(msg),
-        Err(e) => {println!("Decode error {} ", e); None},
+    let (id, read) = match bincode::decode_from_slice::<u32, _>(buf, config) {
+        Ok(decoded) => decoded,
+        Err(e) => {println!("Decode error {} ", e); return None},
+    };
+
+    let codecs = MESSAGE_CODECS.read().unwrap();
+
+    match codecs.codecs.get(&id) {
+        Some(codec) => (codec.decode)(&buf[read..]),
+        None => {println!("Unknown message id {} ", id); None},
     }
 }
 
-pub fn register_message_type<T: MessageTrait>(app: &mut App, network_side: &NetworkSide){
+pub(crate) fn queue_message_dispatch(commands: &mut Commands, message: Box<dyn MessageTrait>, message_type: ConnectionsType, uuid: Option<Uuid>, network_side: NetworkSide, connection_name: &'static str) {
+    commands.queue(move |w: &mut World| {
+        w.resource_scope::<MessageDispatchers, _>(|w, dispatchers| {
+            let type_id = message.as_any().type_id();
+
+            if let Some(dispatcher) = dispatchers.0.get(&type_id) {
+                let boxed_any = message as Box<dyn Any>;
+
+                dispatcher(boxed_any, w, message_type, uuid, &network_side, connection_name);
+            } else {
+                println!("This message does not exist");
+            }
+        });
+    });
+}
+
+pub fn register_message_type<T: MessageTrait + Serialize + DeserializeOwned>(app: &mut App, network_side: &NetworkSide){
     if network_side == &NetworkSide::Client {
         app.add_message::<MessageReceivedFromServer<T>>();
     }else if network_side == &NetworkSide::Server {
@@ -92,5 +261,145 @@ pub fn register_message_type<T: MessageTrait>(app: &mut App, network_side: &Netw
         app.add_message::<MessageReceivedFromClient<T>>();
     }
 
-    register_message_type!(T, &DISPATCHERS);
-}
\ No newline at end of file
+    MESSAGE_CODECS.write().unwrap().register::<T>();
+    app.world_mut().get_resource_or_init::<ProtocolSchema>().add_message::<T>();
+
+    let mut dispatchers = app.world_mut().get_resource_or_init::<MessageDispatchers>();
+
+    register_message_type!(T, dispatchers.0);
+}
+
+/// Panics if `T` was already registered in this App with another channel.
+pub fn register_message_type_with_channel<T: MessageTrait + Serialize + DeserializeOwned>(app: &mut App, network_side: &NetworkSide, channel: MessageChannel){
+    register_message_type::<T>(app, network_side);
+
+    let dispatchers = app.world_mut().resource::<MessageDispatchers>();
+    let mut routes = dispatchers.1.write().unwrap();
+
+    if let Some(registered) = routes.channels.insert(TypeId::of::<T>(), channel)
+        && registered != channel {
+        panic!("Message type {} was registered with the channels {:?} and {:?}", wire_name::<T>(), registered, channel);
+    }
+}
+
+/// Panics if `T` was already registered in this App with another direction.
+pub fn register_message_type_with_direction<T: MessageTrait + Serialize + DeserializeOwned>(app: &mut App, network_side: &NetworkSide, direction: MessageDirection){
+    register_message_type::<T>(app, network_side);
+
+    let dispatchers = app.world_mut().resource::<MessageDispatchers>();
+    let mut routes = dispatchers.1.write().unwrap();
+
+    if let Some(registered) = routes.directions.insert(TypeId::of::<T>(), direction)
+        && registered != direction {
+        panic!("Message type {} was registered with the directions {:?} and {:?}", wire_name::<T>(), registered, direction);
+    }
+}
+
+impl MessageRoutes {
+    pub(crate) fn channel(&self, message: &dyn MessageTrait) -> MessageChannel {
+        match self.channels.get(&message.as_any().type_id()) {
+            Some(channel) => *channel,
+            None => message.channel()
+        }
+    }
+
+    pub(crate) fn direction(&self, message: &dyn MessageTrait) -> MessageDirection {
+        match self.directions.get(&message.as_any().type_id()) {
+            Some(direction) => *direction,
+            None => message.direction()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message_derive::Message;
+    use serde::Deserialize;
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Message, Clone)]
+    struct Routed;
+
+    mod client {
+        use super::*;
+
+        #[derive(Serialize, Deserialize, Message, Clone)]
+        pub struct Chat;
+
+        #[derive(Serialize, Deserialize, Message, Clone)]
+        #[message(id = 7)]
+        pub struct Numbered;
+    }
+
+    mod server {
+        use super::*;
+
+        #[derive(Serialize, Deserialize, Message, Clone)]
+        pub struct Chat;
+
+        #[derive(Serialize, Deserialize, Message, Clone)]
+        #[message(id = 7)]
+        pub struct Renamed;
+    }
+
+    fn routes(app: &App) -> SharedMessageRoutes {
+        Arc::clone(&app.world().resource::<MessageDispatchers>().1)
+    }
+
+    #[test]
+    fn routes_belong_to_their_app() {
+        let mut unreliable = App::new();
+        let mut server_only = App::new();
+
+        register_message_type_with_channel::<Routed>(&mut unreliable, &NetworkSide::Client, MessageChannel::Unreliable);
+        register_message_type_with_direction::<Routed>(&mut server_only, &NetworkSide::Client, MessageDirection::ServerToClient);
+
+        let unreliable = routes(&unreliable);
+        let server_only = routes(&server_only);
+
+        assert_eq!(unreliable.read().unwrap().channel(&Routed), MessageChannel::Unreliable);
+        assert_eq!(unreliable.read().unwrap().direction(&Routed), MessageDirection::Both);
+        assert_eq!(server_only.read().unwrap().channel(&Routed), MessageChannel::ReliableOrdered);
+        assert_eq!(server_only.read().unwrap().direction(&Routed), MessageDirection::ServerToClient);
+    }
+
+    #[test]
+    fn same_names_in_other_modules_do_not_collide() {
+        let mut codecs = MessageCodecs::default();
+
+        codecs.register::<client::Chat>();
+        codecs.register::<server::Chat>();
+
+        assert_ne!(message_wire_id::<client::Chat>(), message_wire_id::<server::Chat>());
+        assert_eq!(codecs.codecs.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Give one of them another id with #[message(id = ...)]")]
+    fn same_ids_collide() {
+        let mut codecs = MessageCodecs::default();
+
+        codecs.register::<client::Numbered>();
+        codecs.register::<server::Renamed>();
+    }
+
+    #[test]
+    fn explicit_ids_avoid_collisions() {
+        let mut codecs = MessageCodecs::default();
+
+        codecs.register::<client::Chat>();
+        codecs.register::<server::Renamed>();
+
+        assert_eq!(message_wire_id::<server::Renamed>(), 7);
+        assert_eq!(codecs.codecs.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "registered with the channels")]
+    fn conflicting_channels_panic() {
+        let mut app = App::new();
+
+        register_message_type_with_channel::<Routed>(&mut app, &NetworkSide::Client, MessageChannel::Unreliable);
+        register_message_type_with_channel::<Routed>(&mut app, &NetworkSide::Server, MessageChannel::ReliableUnordered);
+    }
+}
@@ -1 +1,3 @@
-﻿pub mod messaging;
\ No newline at end of file
+﻿pub mod batching;
+pub mod messaging;
+pub mod protocol;
\ No newline at end of file
@@ -0,0 +1,110 @@
+use std::any::type_name;
+use crate::systems::messaging::{message_wire_id, MessageTrait};
+use std::collections::BTreeMap;
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+
+/// Version of the crate's own wire protocol, bumped on every breaking change to it.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// What both sides must agree on before a client is admitted: the wire protocol, the version
+/// of the game set with `set_app_version`, every registered message type and every replicated
+/// component. Message types count whatever side they were registered for, so shared message
+/// types have to be registered in both builds.
+#[derive(Resource, Default)]
+pub struct ProtocolSchema {
+    pub(crate) app_version: u32,
+    pub(crate) messages: BTreeMap<u32, String>,
+    pub(crate) components: BTreeMap<i32, &'static str>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProtocolInfo {
+    pub version: u32,
+    pub app_version: u32,
+    pub schema_hash: u64
+}
+
+/// FNV-1a, unlike `DefaultHasher` it gives the same hash in every build.
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash
+}
+
+/// The name a message type has in the schema and in errors, the type name without module paths,
+/// generic arguments included: `Wrapper<foo::A>` is `Wrapper<A>`.
+pub(crate) fn wire_name<T>() -> String {
+    let mut name = String::new();
+
+    for part in type_name::<T>().split_inclusive(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':')) {
+        let (path, delimiter) = match part.char_indices().last() {
+            Some((i, c)) if !(c.is_alphanumeric() || c == '_' || c == ':') => part.split_at(i),
+            _ => (part, "")
+        };
+
+        name.push_str(path.rsplit("::").next().unwrap_or(path));
+        name.push_str(delimiter);
+    }
+
+    name
+}
+
+impl ProtocolSchema {
+    pub fn set_app_version(&mut self, app_version: u32) {
+        self.app_version = app_version;
+    }
+
+    pub(crate) fn add_message<T: MessageTrait>(&mut self) {
+        self.messages.insert(message_wire_id::<T>(), wire_name::<T>());
+    }
+
+    pub(crate) fn add_component(&mut self, id: i32, type_path: &'static str) {
+        self.components.insert(id, type_path);
+    }
+
+    pub(crate) fn info(&self) -> ProtocolInfo {
+        let mut schema = String::new();
+
+        for (id, message) in &self.messages {
+            schema.push_str(&format!("{}={};", id, message));
+        }
+
+        for (id, component) in &self.components {
+            schema.push_str(&format!("{}={};", id, component));
+        }
+
+        ProtocolInfo {
+            version: PROTOCOL_VERSION,
+            app_version: self.app_version,
+            schema_hash: stable_hash(schema.as_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod foo {
+        pub struct A;
+    }
+
+    mod bar {
+        pub struct A;
+    }
+
+    struct Wrapper<T>(T);
+
+    #[test]
+    fn wire_names_keep_generic_arguments() {
+        assert_eq!(wire_name::<Wrapper<foo::A>>(), "Wrapper<A>");
+        assert_eq!(wire_name::<Wrapper<(foo::A, bar::A)>>(), "Wrapper<(A, A)>");
+        assert_eq!(wire_name::<std::collections::HashMap<u32, Vec<foo::A>>>(), "HashMap<u32, Vec<A>>");
+    }
+}
@@ -1,9 +1,12 @@
-﻿use bevy::prelude::{ResMut};
-use inator::connections::{ClientConnections, Connections};
-use inator::connections::tcp::client::ClientTcpSettings;
+﻿use bevy::prelude::{Res, ResMut};
+use inator::connections::{ClientConnections, Connections, ServerConnections};
+use inator::connections::memory::client::ClientMemorySettings;
 
 pub fn create_connection(
     mut client_connections: ResMut<ClientConnections>,
+    server_connections: Res<ServerConnections>,
 ){
-    client_connections.new_client_tcp_connection(ClientTcpSettings::default(),"Lobby");
+    if let Some(connector) = server_connections.memory_connector("Lobby") {
+        client_connections.new_client_memory_connection(ClientMemorySettings::new(connector),"Lobby");
+    }
 }
\ No newline at end of file
@@ -1,10 +1,10 @@
 ﻿use bevy::prelude::ResMut;
 use inator::connections::{Connections, ServerConnections};
 
-use inator::connections::tcp::server::ServerTcpSettings;
+use inator::connections::memory::server::ServerMemorySettings;
 
 pub fn create_connection(
     mut server_connections: ResMut<ServerConnections>,
 ){
-    server_connections.new_server_tcp_connection(ServerTcpSettings::default(),"Lobby");
+    server_connections.new_server_memory_connection(ServerMemorySettings::default(),"Lobby");
 }
\ No newline at end of file
@@ -1,19 +1,81 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput};
+use syn::{parse_macro_input, DeriveInput, Ident, LitInt};
 
-#[proc_macro_derive(Message)]
+/// Implements `MessageTrait` for the type, which must also implement `Clone`.
+///
+/// The channel a message is sent on can be picked with `#[message(channel = UnreliableSequenced)]`,
+/// using any `MessageChannel` variant. `MessageChannel` must be in scope, like `MessageTrait`.
+///
+/// `#[message(direction = ServerToClient)]` restricts who may send it, using any `MessageDirection`
+/// variant, which must then be in scope too.
+///
+/// The id sent on the wire is a hash of the full type path, so client and server have to share
+/// the type. `#[message(id = 12)]` sets it instead, registering two types with the same id panics.
+/// The type must also implement `Serialize` and `Deserialize`.
+#[proc_macro_derive(Message, attributes(message))]
 pub fn derive_message(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
     let name = ast.ident;
+    let mut channel: Option<Ident> = None;
+    let mut id: Option<LitInt> = None;
+    let mut direction: Option<Ident> = None;
+
+    for attr in ast.attrs.iter().filter(|attr| attr.path().is_ident("message")) {
+        let result = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("channel") {
+                channel = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("direction") {
+                direction = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported message attribute"))
+            }
+        });
+
+        if let Err(e) = result {
+            return e.to_compile_error().into();
+        }
+    }
+
+    let channel_fn = channel.map(|channel| quote! {
+        fn channel(&self) -> MessageChannel {
+            MessageChannel::#channel
+        }
+    });
+
+    let direction_fn = direction.map(|direction| quote! {
+        fn direction(&self) -> MessageDirection {
+            MessageDirection::#direction
+        }
+    });
+
+    let id_fn = id.map(|id| quote! {
+        fn message_id() -> Option<u32> {
+            Some(#id)
+        }
+    });
 
     let expanded = quote! {
-        #[typetag::serde]
         impl MessageTrait for #name {
             fn as_any(&self) -> &dyn std::any::Any {
                 self
             }
+
+            fn clone_message(&self) -> Box<dyn MessageTrait> {
+                Box::new(self.clone())
+            }
+
+            #channel_fn
+
+            #direction_fn
+
+            #id_fn
         }
     };
 